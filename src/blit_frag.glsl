@@ -0,0 +1,11 @@
+#version 330 core
+
+uniform sampler2D u_image;
+
+in vec2 vUV;
+
+out vec4 FragColor;
+
+void main() {
+  FragColor = vec4(texture(u_image, vUV).rgb, 1.0);
+}
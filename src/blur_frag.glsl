@@ -0,0 +1,26 @@
+#version 330 core
+
+#define MAX_RADIUS 32
+
+uniform sampler2D u_image;
+// One texel along the blur axis, e.g: (1/width, 0) for the horizontal pass
+uniform vec2 u_direction;
+uniform int u_radius;
+// Normalized one-sided kernel, the center weight is u_weights[0]
+uniform float u_weights[MAX_RADIUS + 1];
+
+in vec2 vUV;
+
+out vec4 FragColor;
+
+void main() {
+  vec4 sum = texture(u_image, vUV) * u_weights[0];
+
+  for (int i = 1; i <= u_radius; i++) {
+    vec2 offset = u_direction * float(i);
+    sum += texture(u_image, vUV + offset) * u_weights[i];
+    sum += texture(u_image, vUV - offset) * u_weights[i];
+  }
+
+  FragColor = sum;
+}
@@ -0,0 +1,468 @@
+use glam::Vec3;
+
+/// Spheres per leaf before a node is split
+const MAX_LEAF_SIZE: usize = 2;
+/// Candidate split planes per axis for the surface area heuristic
+const SAH_BINS: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+struct Sphere {
+    center: Vec3,
+    radius: f32,
+    id: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Aabb {
+            min: Vec3::splat(f32::INFINITY),
+            max: Vec3::splat(f32::NEG_INFINITY),
+        }
+    }
+
+    fn of_sphere(sphere: &Sphere) -> Self {
+        Aabb {
+            min: sphere.center - Vec3::splat(sphere.radius),
+            max: sphere.center + Vec3::splat(sphere.radius),
+        }
+    }
+
+    fn union(self, other: Aabb) -> Self {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    fn surface_area(&self) -> f32 {
+        let d = (self.max - self.min).max(Vec3::ZERO);
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Entry distance of the ray into the box if it hits closer than `max_t`
+    fn hit(&self, origin: Vec3, inv_dir: Vec3, max_t: f32) -> Option<f32> {
+        let t0 = (self.min - origin) * inv_dir;
+        let t1 = (self.max - origin) * inv_dir;
+        // 0 * inf is NaN for a ray along one of the slab's planes, it never leaves that slab
+        let along_plane = t0.is_nan_mask() | t1.is_nan_mask();
+        let near = Vec3::select(along_plane, Vec3::NEG_INFINITY, t0.min(t1));
+        let far = Vec3::select(along_plane, Vec3::INFINITY, t0.max(t1));
+        let t_near = near.max_element().max(0.0);
+        let t_far = far.min_element().min(max_t);
+        (t_near <= t_far).then_some(t_near)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Aabb,
+    /// Index of the first sphere for leaves, of the left child (right is `left + 1`) otherwise
+    first: usize,
+    /// Sphere count, 0 for inner nodes
+    count: usize,
+}
+
+/// Bounding volume hierarchy over spheres for CPU-side ray picking (click-selection)
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    spheres: Vec<Sphere>,
+}
+
+impl Bvh {
+    /// Builds the tree from `(center, radius, id)` triples
+    pub fn build(spheres: &[([f32; 3], f32, u32)]) -> Bvh {
+        let mut bvh = Bvh::default();
+        bvh.rebuild(spheres);
+        bvh
+    }
+
+    /// Rebuilds the tree in place, e.g: after objects moved
+    pub fn rebuild(&mut self, spheres: &[([f32; 3], f32, u32)]) {
+        self.spheres = spheres
+            .iter()
+            .map(|&(center, radius, id)| Sphere {
+                center: Vec3::from(center),
+                radius,
+                id,
+            })
+            .collect();
+        self.nodes.clear();
+
+        if self.spheres.is_empty() {
+            return;
+        }
+        self.nodes.push(Node {
+            bounds: Aabb::empty(),
+            first: 0,
+            count: self.spheres.len(),
+        });
+        self.subdivide(0);
+    }
+
+    /// Id of the closest sphere hit by the ray, `ray_dir` doesn't need to be normalized
+    pub fn intersect(&self, ray_origin: [f32; 3], ray_dir: [f32; 3]) -> Option<u32> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let origin = Vec3::from(ray_origin);
+        let dir = Vec3::from(ray_dir);
+        let inv_dir = dir.recip();
+
+        let mut closest: Option<(f32, u32)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let max_t = closest.map_or(f32::INFINITY, |(t, _)| t);
+            if node.bounds.hit(origin, inv_dir, max_t).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for sphere in &self.spheres[node.first..node.first + node.count] {
+                    if let Some(t) = ray_sphere(origin, dir, sphere)
+                        && closest.is_none_or(|(best, _)| t < best)
+                    {
+                        closest = Some((t, sphere.id));
+                    }
+                }
+            } else {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+            }
+        }
+
+        closest.map(|(_, id)| id)
+    }
+
+    fn subdivide(&mut self, index: usize) {
+        let Node { first, count, .. } = self.nodes[index];
+        let spheres = &mut self.spheres[first..first + count];
+
+        let bounds = spheres
+            .iter()
+            .fold(Aabb::empty(), |b, s| b.union(Aabb::of_sphere(s)));
+        self.nodes[index].bounds = bounds;
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        let split = sah_split(spheres).unwrap_or_else(|| median_split(spheres, bounds));
+        if split == 0 || split == count {
+            return;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: Aabb::empty(),
+            first,
+            count: split,
+        });
+        self.nodes.push(Node {
+            bounds: Aabb::empty(),
+            first: first + split,
+            count: count - split,
+        });
+        self.nodes[index].first = left;
+        self.nodes[index].count = 0;
+
+        self.subdivide(left);
+        self.subdivide(left + 1);
+    }
+}
+
+/// Partitions `spheres` at the cheapest binned SAH plane, returns the size of the left half
+fn sah_split(spheres: &mut [Sphere]) -> Option<usize> {
+    let centroids = spheres.iter().fold(Aabb::empty(), |b, s| {
+        b.union(Aabb {
+            min: s.center,
+            max: s.center,
+        })
+    });
+    let extent = centroids.max - centroids.min;
+
+    let mut best: Option<(f32, usize, f32)> = None;
+    for axis in 0..3 {
+        if extent[axis] <= f32::EPSILON {
+            continue;
+        }
+        for bin in 1..SAH_BINS {
+            let plane = centroids.min[axis] + extent[axis] * bin as f32 / SAH_BINS as f32;
+
+            let (mut left, mut right) = (Aabb::empty(), Aabb::empty());
+            let (mut left_count, mut right_count) = (0, 0);
+            for sphere in spheres.iter() {
+                if sphere.center[axis] < plane {
+                    left = left.union(Aabb::of_sphere(sphere));
+                    left_count += 1;
+                } else {
+                    right = right.union(Aabb::of_sphere(sphere));
+                    right_count += 1;
+                }
+            }
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let cost =
+                left.surface_area() * left_count as f32 + right.surface_area() * right_count as f32;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, plane));
+            }
+        }
+    }
+
+    let (_, axis, plane) = best?;
+    Some(partition(spheres, |s| s.center[axis] < plane))
+}
+
+/// Fallback for coincident centers: splits the sorted spheres in half along the longest axis
+fn median_split(spheres: &mut [Sphere], bounds: Aabb) -> usize {
+    let axis = (bounds.max - bounds.min).max_position();
+    spheres.sort_by(|a, b| a.center[axis].total_cmp(&b.center[axis]));
+    spheres.len() / 2
+}
+
+fn partition(spheres: &mut [Sphere], is_left: impl Fn(&Sphere) -> bool) -> usize {
+    let mut split = 0;
+    for i in 0..spheres.len() {
+        if is_left(&spheres[i]) {
+            spheres.swap(i, split);
+            split += 1;
+        }
+    }
+    split
+}
+
+/// Closest non-negative ray parameter where the ray meets the sphere
+fn ray_sphere(origin: Vec3, dir: Vec3, sphere: &Sphere) -> Option<f32> {
+    let oc = origin - sphere.center;
+    let a = dir.length_squared();
+    let half_b = oc.dot(dir);
+    let c = oc.length_squared() - sphere.radius * sphere.radius;
+
+    let discriminant = half_b * half_b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt_d = discriminant.sqrt();
+    let near = (-half_b - sqrt_d) / a;
+    let far = (-half_b + sqrt_d) / a;
+    // The origin may be inside the sphere
+    [near, far].into_iter().find(|&t| t >= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Spheres = Vec<([f32; 3], f32, u32)>;
+
+    /// xorshift32, keeps the tests reproducible without a rand dependency
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+
+        fn vec3(&mut self, min: f32, max: f32) -> [f32; 3] {
+            [
+                self.range(min, max),
+                self.range(min, max),
+                self.range(min, max),
+            ]
+        }
+    }
+
+    fn random_spheres(rng: &mut Rng, count: u32) -> Spheres {
+        (0..count)
+            .map(|id| (rng.vec3(-20.0, 20.0), rng.range(0.1, 2.0), id))
+            .collect()
+    }
+
+    fn hit_distance(spheres: &Spheres, id: u32, origin: [f32; 3], dir: [f32; 3]) -> Option<f32> {
+        let &(center, radius, id) = spheres.iter().find(|s| s.2 == id)?;
+        let sphere = Sphere {
+            center: Vec3::from(center),
+            radius,
+            id,
+        };
+        ray_sphere(Vec3::from(origin), Vec3::from(dir), &sphere)
+    }
+
+    /// Closest hit distance by testing every sphere
+    fn brute_force(spheres: &Spheres, origin: [f32; 3], dir: [f32; 3]) -> Option<f32> {
+        spheres
+            .iter()
+            .filter_map(|s| hit_distance(spheres, s.2, origin, dir))
+            .min_by(f32::total_cmp)
+    }
+
+    /// Spheres can tie for the closest hit, so the distances are compared instead of the ids
+    fn assert_matches_brute_force(bvh: &Bvh, spheres: &Spheres, origin: [f32; 3], dir: [f32; 3]) {
+        let expected = brute_force(spheres, origin, dir);
+        let actual = bvh
+            .intersect(origin, dir)
+            .map(|id| hit_distance(spheres, id, origin, dir).expect("the hit sphere is hit"));
+        match (actual, expected) {
+            (None, None) => {}
+            (Some(actual), Some(expected)) => assert!(
+                (actual - expected).abs() <= 1e-4 * expected.max(1.0),
+                "ray {:?} -> {:?}: hit at {} instead of {}",
+                origin,
+                dir,
+                actual,
+                expected
+            ),
+            _ => panic!(
+                "ray {:?} -> {:?}: bvh hit at {:?}, brute force at {:?}",
+                origin, dir, actual, expected
+            ),
+        }
+    }
+
+    #[test]
+    fn intersect_matches_brute_force_on_random_spheres() {
+        let mut rng = Rng(0x1234_5678);
+        let spheres = random_spheres(&mut rng, 200);
+        let bvh = Bvh::build(&spheres);
+
+        for _ in 0..1000 {
+            let origin = rng.vec3(-30.0, 30.0);
+            let target = rng.vec3(-20.0, 20.0);
+            let dir = [
+                target[0] - origin[0],
+                target[1] - origin[1],
+                target[2] - origin[2],
+            ];
+            assert_matches_brute_force(&bvh, &spheres, origin, dir);
+        }
+    }
+
+    #[test]
+    fn intersect_handles_coincident_centers() {
+        // SAH has no plane to split on, the median split has to take over
+        let mut spheres: Spheres = (0..16)
+            .map(|id| ([1.0, 2.0, 3.0], 0.5 + id as f32 * 0.1, id))
+            .collect();
+        spheres.push(([1.0, 2.0, 10.0], 0.5, 16));
+        let bvh = Bvh::build(&spheres);
+
+        assert_eq!(bvh.intersect([1.0, 2.0, -10.0], [0.0, 0.0, 1.0]), Some(15));
+        assert_eq!(bvh.intersect([1.0, 2.0, 20.0], [0.0, 0.0, -1.0]), Some(16));
+        let mut rng = Rng(42);
+        for _ in 0..200 {
+            let origin = rng.vec3(-10.0, 10.0);
+            let dir = [
+                1.0 - origin[0],
+                2.0 - origin[1],
+                rng.range(3.0, 10.0) - origin[2],
+            ];
+            assert_matches_brute_force(&bvh, &spheres, origin, dir);
+        }
+    }
+
+    #[test]
+    fn intersect_from_inside_a_sphere() {
+        let mut rng = Rng(7);
+        let mut spheres = random_spheres(&mut rng, 50);
+        spheres.push(([0.0, 0.0, 0.0], 50.0, 999));
+        let bvh = Bvh::build(&spheres);
+
+        // From inside, the hit is the far side of the sphere
+        let lonely = vec![([0.0, 0.0, 0.0], 5.0, 7)];
+        assert_eq!(
+            Bvh::build(&lonely).intersect([1.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            Some(7)
+        );
+        for _ in 0..200 {
+            let origin = rng.vec3(-10.0, 10.0);
+            let dir = rng.vec3(-1.0, 1.0);
+            assert_matches_brute_force(&bvh, &spheres, origin, dir);
+        }
+    }
+
+    #[test]
+    fn intersect_handles_axis_parallel_rays() {
+        let mut rng = Rng(99);
+        let mut spheres = random_spheres(&mut rng, 100);
+        // Integer positions so rays start exactly on box planes, where 0 * inf gives NaN
+        let pair = vec![([0.0, 0.0, 0.0], 1.0, 100), ([4.0, 0.0, 0.0], 1.0, 101)];
+        let pair_bvh = Bvh::build(&pair);
+        spheres.extend_from_slice(&pair);
+        let bvh = Bvh::build(&spheres);
+
+        assert_eq!(
+            pair_bvh.intersect([-10.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            Some(100)
+        );
+        assert_eq!(
+            pair_bvh.intersect([10.0, 0.0, 0.0], [-1.0, 0.0, 0.0]),
+            Some(101)
+        );
+        // Grazes the sphere, starting on the y plane of its box
+        assert_eq!(
+            pair_bvh.intersect([4.0, -1.0, -10.0], [0.0, 0.0, 1.0]),
+            Some(101)
+        );
+        let axes = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        for _ in 0..200 {
+            let origin = rng.vec3(-25.0, 25.0).map(f32::round);
+            for dir in axes {
+                assert_matches_brute_force(&bvh, &spheres, origin, dir);
+            }
+        }
+    }
+
+    #[test]
+    fn rebuild_follows_moved_spheres() {
+        let mut rng = Rng(2024);
+        let mut spheres = random_spheres(&mut rng, 100);
+        let mut bvh = Bvh::build(&spheres);
+        let probe = ([0.0, 100.0, -10.0], [0.0, 0.0, 1.0]);
+        assert_eq!(bvh.intersect(probe.0, probe.1), None);
+
+        for sphere in &mut spheres {
+            sphere.0[1] += 100.0;
+        }
+        spheres.push(([0.0, 100.0, 0.0], 1.0, 1000));
+        bvh.rebuild(&spheres);
+
+        assert!(bvh.intersect(probe.0, probe.1).is_some());
+        for _ in 0..500 {
+            let origin = rng.vec3(-30.0, 30.0);
+            let target = rng.vec3(-20.0, 20.0);
+            let dir = [
+                target[0] - origin[0],
+                target[1] + 100.0 - origin[1],
+                target[2] - origin[2],
+            ];
+            assert_matches_brute_force(&bvh, &spheres, origin, dir);
+        }
+
+        bvh.rebuild(&[]);
+        assert_eq!(bvh.intersect(probe.0, probe.1), None);
+    }
+}
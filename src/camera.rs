@@ -0,0 +1,118 @@
+use crate::{math::Vec3, objects::Uniform};
+
+/// Pitch stays just short of straight up/down so the view never flips
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// First person camera, yaw 0 and pitch 0 look down -Z. Angles are in radians
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov_degrees: f32,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(position: impl Into<Vec3>, aspect: f32) -> Self {
+        Camera {
+            position: position.into(),
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_degrees: 60.0,
+            aspect,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+
+    /// Unit vector the camera looks along
+    pub fn forward(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch]
+    }
+
+    /// Unit vector to the right of the view direction, always horizontal
+    pub fn right(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        [cos_yaw, 0.0, sin_yaw]
+    }
+
+    pub fn up(&self) -> [f32; 3] {
+        cross(self.right(), self.forward())
+    }
+
+    /// World to view space, column-major like GLSL's mat4
+    pub fn view_matrix(&self) -> [f32; 16] {
+        let f = self.forward();
+        let r = self.right();
+        let u = self.up();
+        let p = self.position.to_array();
+
+        #[rustfmt::skip]
+        let view = [
+            r[0], u[0], -f[0], 0.0,
+            r[1], u[1], -f[1], 0.0,
+            r[2], u[2], -f[2], 0.0,
+            -dot(r, p), -dot(u, p), dot(f, p), 1.0,
+        ];
+        view
+    }
+
+    /// OpenGL perspective projection (depth -1..1), column-major like GLSL's mat4
+    pub fn projection_matrix(&self) -> [f32; 16] {
+        let focal = 1.0 / (self.fov_degrees.to_radians() / 2.0).tan();
+        let depth = self.near - self.far;
+
+        #[rustfmt::skip]
+        let projection = [
+            focal / self.aspect, 0.0, 0.0, 0.0,
+            0.0, focal, 0.0, 0.0,
+            0.0, 0.0, (self.far + self.near) / depth, -1.0,
+            0.0, 0.0, 2.0 * self.far * self.near / depth, 0.0,
+        ];
+        projection
+    }
+
+    /// Writes both matrices, the uniforms' program has to be the current one
+    pub fn upload(&self, u_view: &Uniform, u_proj: &Uniform) {
+        u_view.set_mat4f(&self.view_matrix(), false);
+        u_proj.set_mat4f(&self.projection_matrix(), false);
+    }
+
+    /// Moves along the view direction, negative `speed` moves backwards
+    pub fn move_forward(&mut self, speed: f32) {
+        self.translate(self.forward(), speed);
+    }
+
+    /// Strafes sideways, negative `speed` moves to the left
+    pub fn move_right(&mut self, speed: f32) {
+        self.translate(self.right(), speed);
+    }
+
+    pub fn rotate(&mut self, dyaw: f32, dpitch: f32) {
+        self.yaw = (self.yaw + dyaw) % std::f32::consts::TAU;
+        self.pitch = (self.pitch + dpitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    fn translate(&mut self, direction: [f32; 3], distance: f32) {
+        self.position.x += direction[0] * distance;
+        self.position.y += direction[1] * distance;
+        self.position.z += direction[2] * distance;
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
@@ -0,0 +1,273 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use gl::types::{GLint, GLvoid};
+
+use crate::{
+    objects::{Framebuffer, GlError},
+    render_state::{GlStateCache, with_pixel_store},
+};
+
+/// Largest payload of an uncompressed (stored) deflate block
+const MAX_STORED_BLOCK: usize = 65535;
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Writes tightly packed RGBA8 `data` (top row first) as a PNG file. Framebuffer::read_color(..)
+/// returns the bottom row first, flip the rows before passing them in.
+/// The image data is zlib wrapped but stored uncompressed, so nothing beyond std is needed
+pub fn write_png(path: &Path, width: u32, height: u32, data: &[u8]) -> Result<(), io::Error> {
+    let row_len = width as usize * 4;
+    if width == 0 || height == 0 || data.len() != row_len * height as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} bytes of pixel data don't match a {}x{} RGBA8 image",
+                data.len(),
+                width,
+                height
+            ),
+        ));
+    }
+
+    // Every scanline starts with its filter type, 0 is none
+    let mut scanlines = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in data.chunks_exact(row_len) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = Vec::new();
+    png.extend_from_slice(PNG_SIGNATURE);
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    fs::File::create(path)?.write_all(&png)
+}
+
+/// Saves the back buffer of the default framebuffer as a PNG, call it after drawing and
+/// before swapping the window
+pub fn capture_screenshot(width: u32, height: u32, path: &Path) -> Result<(), GlError> {
+    let row_len = width as usize * 4;
+    let mut pixels: Vec<u8> = vec![0; row_len * height as usize];
+
+    unsafe {
+        GlStateCache::bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+        gl::ReadBuffer(gl::BACK);
+        with_pixel_store(gl::PACK_ALIGNMENT, 1, || {
+            gl::ReadPixels(
+                0,
+                0,
+                width as GLint,
+                height as GLint,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut GLvoid,
+            )
+        });
+    }
+
+    write_png(path, width, height, &flip_rows(&pixels, width))?;
+    Ok(())
+}
+
+/// Saves color attachment 0 of `fbo` as a PNG, e.g: the target of a hidden window's frames
+/// since reading back a hidden window's default framebuffer is undefined
+pub fn capture_framebuffer(fbo: &Framebuffer, path: &Path) -> Result<(), GlError> {
+    let (pixels, (width, height)) = fbo.read_color(0);
+    write_png(path, width, height, &flip_rows(&pixels, width))?;
+    Ok(())
+}
+
+/// ReadPixels starts at the bottom row, PNG at the top one
+fn flip_rows(pixels: &[u8], width: u32) -> Vec<u8> {
+    pixels
+        .chunks_exact(width as usize * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(crc32_update(0xFFFF_FFFF, kind), data);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// zlib stream made of stored deflate blocks, `data` must not be empty
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // CM 8 (deflate) with a 32K window, no preset dictionary, FCHECK makes it a multiple of 31
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        // BFINAL bit, BTYPE 00 (stored)
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b may overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Bitwise CRC-32 (ISO 3309) as PNG uses it, continues from the running `crc`
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !crc32_update(crc, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pixel(x: u32, y: u32) -> [u8; 4] {
+        [
+            ((x * 37 + y * 11) & 255) as u8,
+            ((y * 29) & 255) as u8,
+            ((x * y * 7) & 255) as u8,
+            (255 - (x * 9) % 256) as u8,
+        ]
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("opengl_blackhole_{}_{}", std::process::id(), name))
+    }
+
+    /// Writes a `width`x`height` image of `test_pixel`s and decodes it again with the image crate
+    fn round_trip(name: &str, width: u32, height: u32) {
+        let pixels: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).flat_map(move |x| test_pixel(x, y)))
+            .collect();
+        let path = temp_path(name);
+
+        write_png(&path, width, height, &pixels).unwrap();
+        let decoded = image::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        let decoded = decoded.unwrap().into_rgba8();
+        assert_eq!(decoded.dimensions(), (width, height));
+        assert!(
+            decoded.into_raw() == pixels,
+            "{}x{} pixels differ",
+            width,
+            height
+        );
+    }
+
+    #[test]
+    fn write_png_round_trips_through_the_image_crate() {
+        round_trip("small.png", 7, 3);
+        // More than one stored block of image data
+        round_trip("multi_block.png", 200, 100);
+    }
+
+    #[test]
+    fn write_png_rejects_mismatched_pixel_data() {
+        let path = temp_path("mismatched.png");
+        assert!(write_png(&path, 2, 2, &[0; 15]).is_err());
+        assert!(write_png(&path, 0, 2, &[]).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn crc32_and_adler32_match_known_vectors() {
+        assert_eq!(crc32(0xFFFF_FFFF, b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(0xFFFF_FFFF, b"123456789"), 0xCBF4_3926);
+        // Chunk CRCs cover the type, continuing from it gives the same as one pass
+        assert_eq!(
+            crc32(crc32_update(0xFFFF_FFFF, b"IE"), b"ND"),
+            crc32(0xFFFF_FFFF, b"IEND")
+        );
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+        // Long enough for the modulo to be taken between chunks
+        assert_eq!(adler32(&[0xFF; 100_000]), adler32_slow(&[0xFF; 100_000]));
+    }
+
+    /// Reference Adler-32 taking the modulo after every byte
+    fn adler32_slow(data: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        (b << 16) | a
+    }
+
+    #[test]
+    fn zlib_stored_splits_into_full_blocks() {
+        let data: Vec<u8> = (0..MAX_STORED_BLOCK * 2 + 10)
+            .map(|i| (i * 7) as u8)
+            .collect();
+        let stream = zlib_stored(&data);
+        assert_eq!((u16::from(stream[0]) << 8 | u16::from(stream[1])) % 31, 0);
+
+        // Walk the stored blocks: BFINAL/BTYPE byte, LEN, NLEN, then LEN bytes
+        let mut pos = 2;
+        let mut payload = Vec::new();
+        let mut lens = Vec::new();
+        loop {
+            let last = stream[pos] == 1;
+            let len = u16::from_le_bytes([stream[pos + 1], stream[pos + 2]]);
+            let nlen = u16::from_le_bytes([stream[pos + 3], stream[pos + 4]]);
+            assert_eq!(len, !nlen);
+            payload.extend_from_slice(&stream[pos + 5..pos + 5 + len as usize]);
+            lens.push(len as usize);
+            pos += 5 + len as usize;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(lens, [MAX_STORED_BLOCK, MAX_STORED_BLOCK, 10]);
+        assert!(payload == data);
+        assert_eq!(stream[pos..], adler32(&data).to_be_bytes());
+    }
+}
@@ -0,0 +1,16 @@
+/// Startup settings of the renderer
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: (f32, f32, f32, f32),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            width: 800,
+            height: 800,
+            clear_color: (0.1, 0.1, 0.1, 1.0),
+        }
+    }
+}
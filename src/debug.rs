@@ -0,0 +1,189 @@
+use std::{
+    error::Error,
+    ffi::{CStr, c_void},
+    ptr::null,
+};
+
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+
+use crate::{
+    gpu_info::GpuCaps,
+    objects::{Program, Texture2D, Uniform, Vao, create_program_from_str},
+    render_state::{GlStateCache, ProgramGuard},
+};
+
+/// Shows four textures side by side in the quadrants of the window, e.g: the G-buffer
+/// targets. The order is top-left, top-right, bottom-left, bottom-right
+pub struct QuadSplitView<'a> {
+    pub textures: [&'a Texture2D; 4],
+    program: Program,
+    vao: Vao,
+}
+
+impl<'a> QuadSplitView<'a> {
+    pub fn new(textures: [&'a Texture2D; 4]) -> Result<Self, Box<dyn Error>> {
+        let program = create_program_from_str(
+            include_str!("fullscreen_vert.glsl"),
+            include_str!("blit_frag.glsl"),
+        )?;
+        program.set();
+        Uniform::new(program.id(), "u_image")?.set_1i(0);
+
+        Ok(QuadSplitView {
+            textures,
+            program,
+            vao: Vao::generate(),
+        })
+    }
+
+    /// Draws into the currently bound framebuffer of size `total_width` x `total_height`.
+    /// The scissor box and scissor test are restored afterwards
+    pub fn draw(&self, total_width: u32, total_height: u32) {
+        let half_w = (total_width / 2) as GLint;
+        let half_h = (total_height / 2) as GLint;
+        // GL's origin is the bottom-left corner
+        let regions = [(0, half_h), (half_w, half_h), (0, 0), (half_w, 0)];
+
+        let _guard = ProgramGuard::save_with_viewport();
+        let mut previous_scissor: [GLint; 4] = [0; 4];
+        let scissor_enabled = GlStateCache::is_enabled(gl::SCISSOR_TEST);
+        unsafe {
+            gl::GetIntegerv(gl::SCISSOR_BOX, previous_scissor.as_mut_ptr());
+        }
+        GlStateCache::enable(gl::SCISSOR_TEST);
+
+        self.program.set();
+        self.vao.bind();
+        for (texture, (x, y)) in self.textures.iter().zip(regions) {
+            texture.bind(0);
+            unsafe {
+                gl::Viewport(x, y, half_w, half_h);
+                gl::Scissor(x, y, half_w, half_h);
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+        }
+
+        GlStateCache::set(gl::SCISSOR_TEST, scissor_enabled);
+        unsafe {
+            let [x, y, w, h] = previous_scissor;
+            gl::Scissor(x, y, w, h);
+        }
+    }
+}
+
+/// Prints GL errors and warnings to stderr as they happen via gl::DebugMessageCallback(..),
+/// notifications are filtered out. Needs OpenGL 4.3 or KHR_debug, otherwise this returns false
+pub fn enable_debug_output() -> bool {
+    if !GpuCaps::current().khr_debug {
+        return false;
+    }
+
+    GlStateCache::enable(gl::DEBUG_OUTPUT);
+    // Report on the offending call's stack instead of some time later
+    GlStateCache::enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    unsafe {
+        gl::DebugMessageCallback(Some(debug_callback), null());
+        gl::DebugMessageControl(
+            gl::DONT_CARE,
+            gl::DONT_CARE,
+            gl::DEBUG_SEVERITY_NOTIFICATION,
+            0,
+            null(),
+            gl::FALSE,
+        );
+    }
+    true
+}
+
+/// Labelled scope in the GL command stream that RenderDoc and apitrace show as a group,
+/// popped again when dropped, e.g: `let _group = DebugGroup::new(0, "Shadow Pass");`.
+/// Does nothing without OpenGL 4.3 or KHR_debug
+pub struct DebugGroup {
+    pushed: bool,
+}
+
+impl DebugGroup {
+    pub fn new(id: u32, label: &str) -> Self {
+        let pushed = GpuCaps::current().khr_debug;
+        if pushed {
+            unsafe {
+                gl::PushDebugGroup(
+                    gl::DEBUG_SOURCE_APPLICATION,
+                    id,
+                    label.len() as GLsizei,
+                    label.as_ptr() as *const GLchar,
+                );
+            }
+        }
+        DebugGroup { pushed }
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if self.pushed {
+            unsafe {
+                gl::PopDebugGroup();
+            }
+        }
+    }
+}
+
+/// Names a GL object in GPU debuggers, `object_type` is e.g: gl::BUFFER for a Vbo,
+/// gl::TEXTURE or gl::PROGRAM. Does nothing without OpenGL 4.3 or KHR_debug
+pub fn label_object(object_type: GLenum, id: GLuint, label: &str) {
+    if !GpuCaps::current().khr_debug {
+        return;
+    }
+    unsafe {
+        gl::ObjectLabel(
+            object_type,
+            id,
+            label.len() as GLsizei,
+            label.as_ptr() as *const GLchar,
+        );
+    }
+}
+
+extern "system" fn debug_callback(
+    source: GLenum,
+    kind: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    _length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let source = match source {
+        gl::DEBUG_SOURCE_API => "API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "Window System",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "Shader Compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "Third Party",
+        gl::DEBUG_SOURCE_APPLICATION => "Application",
+        _ => "Other",
+    };
+    let kind = match kind {
+        gl::DEBUG_TYPE_ERROR => "Error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "Deprecated",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "Undefined Behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "Portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "Performance",
+        _ => "Other",
+    };
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH => "high",
+        gl::DEBUG_SEVERITY_MEDIUM => "medium",
+        gl::DEBUG_SEVERITY_LOW => "low",
+        _ => "notification",
+    };
+    let message = if message.is_null() {
+        "".into()
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy()
+    };
+
+    eprintln!(
+        "GL {} ({}, {} severity, id {}): {}",
+        kind, source, severity, id, message
+    );
+}
@@ -0,0 +1,167 @@
+use std::error::Error;
+
+use gl::types::GLint;
+
+use crate::{
+    math::OCT_DECODE_GLSL,
+    objects::{
+        FboAttachment, Framebuffer, GlError, InternalFormat, Program, Texture2D, Uniform, Vao,
+        create_program_from_str,
+    },
+    render_state::ProgramGuard,
+};
+
+/// Has to match MAX_LIGHTS in deferred_lighting_frag.glsl
+pub const MAX_LIGHTS: usize = 32;
+
+/// Render targets of the geometry pass. Fragment outputs 0/1/2 are the world position (RGBA16F),
+/// the octahedral encoded normal (RG16F, see `math::oct_encode`) and the albedo (RGBA8)
+pub struct GBuffer {
+    pub fbo: Framebuffer,
+    pub position: Texture2D,
+    pub normal: Texture2D,
+    pub albedo: Texture2D,
+    pub depth: Texture2D,
+}
+
+impl GBuffer {
+    pub fn new(width: u32, height: u32) -> Result<Self, GlError> {
+        let texture = |format| {
+            let mut tex = Texture2D::generate();
+            tex.allocate(width, height, format);
+            tex
+        };
+        let position = texture(InternalFormat::Rgba16F);
+        let normal = texture(InternalFormat::Rg16F);
+        let albedo = texture(InternalFormat::Rgba8);
+        let depth = texture(InternalFormat::Depth24);
+
+        let fbo = Framebuffer::generate(width, height);
+        fbo.attach(FboAttachment::Color(0), &position, 0);
+        fbo.attach(FboAttachment::Color(1), &normal, 0);
+        fbo.attach(FboAttachment::Color(2), &albedo, 0);
+        fbo.attach(FboAttachment::Depth, &depth, 0);
+        fbo.set_draw_buffers(3);
+        fbo.check_status()?;
+
+        Ok(GBuffer {
+            fbo,
+            position,
+            normal,
+            albedo,
+            depth,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.fbo.size()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// Distance at which the light has faded out completely
+    pub radius: f32,
+}
+
+/// The lights of the scene, only the first `MAX_LIGHTS` are uploaded
+#[derive(Clone, Debug, Default)]
+pub struct LightSet {
+    pub lights: Vec<PointLight>,
+}
+
+impl LightSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+}
+
+/// Fullscreen pass that shades the G-buffer with a LightSet into an HDR (RGBA16F) Framebuffer
+pub struct DeferredLightingPass {
+    pub output: Framebuffer,
+    program: Program,
+    vao: Vao,
+    u_light_count: Uniform,
+    u_light_positions: Uniform,
+    u_light_colors: Uniform,
+    u_light_radii: Uniform,
+}
+
+impl DeferredLightingPass {
+    pub fn new(gbuffer: &GBuffer, lights: &LightSet) -> Result<Self, Box<dyn Error>> {
+        let frag = include_str!("deferred_lighting_frag.glsl")
+            .replace("#include \"oct_decode.glsl\"", OCT_DECODE_GLSL);
+        let program = create_program_from_str(include_str!("fullscreen_vert.glsl"), &frag)?;
+
+        program.set();
+        Uniform::new(program.id(), "u_gposition")?.set_1i(0);
+        Uniform::new(program.id(), "u_gnormal")?.set_1i(1);
+        Uniform::new(program.id(), "u_galbedo")?.set_1i(2);
+
+        let (width, height) = gbuffer.size();
+        let pass = DeferredLightingPass {
+            output: Framebuffer::with_format(width, height, InternalFormat::Rgba16F),
+            u_light_count: Uniform::new(program.id(), "u_light_count")?,
+            u_light_positions: Uniform::new(program.id(), "u_light_positions")?,
+            u_light_colors: Uniform::new(program.id(), "u_light_colors")?,
+            u_light_radii: Uniform::new(program.id(), "u_light_radii")?,
+            program,
+            vao: Vao::generate(),
+        };
+        pass.update_lights(lights);
+        pass.output.check_status()?;
+
+        Ok(pass)
+    }
+
+    /// Re-uploads the light arrays, e.g: after lights moved
+    pub fn update_lights(&self, lights: &LightSet) {
+        let lights = &lights.lights[..lights.lights.len().min(MAX_LIGHTS)];
+        let positions: Vec<f32> = lights.iter().flat_map(|l| l.position).collect();
+        let colors: Vec<f32> = lights.iter().flat_map(|l| l.color).collect();
+        let radii: Vec<f32> = lights.iter().map(|l| l.radius).collect();
+
+        let _guard = ProgramGuard::save();
+        self.program.set();
+
+        self.u_light_count.set_1i(lights.len() as i32);
+        unsafe {
+            let count = lights.len() as GLint;
+            gl::Uniform3fv(self.u_light_positions.id, count, positions.as_ptr());
+            gl::Uniform3fv(self.u_light_colors.id, count, colors.as_ptr());
+        }
+        self.u_light_radii.set_1fv(&radii);
+    }
+
+    /// Shades `gbuffer` into `output`
+    pub fn render(&self, gbuffer: &GBuffer) -> &Texture2D {
+        let (width, height) = self.output.size();
+
+        let _guard = ProgramGuard::save_with_viewport();
+        unsafe {
+            gl::Viewport(0, 0, width as GLint, height as GLint);
+        }
+
+        self.output.bind();
+        self.program.set();
+        gbuffer.position.bind(0);
+        gbuffer.normal.bind(1);
+        gbuffer.albedo.bind(2);
+
+        self.vao.bind();
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        self.output.unbind();
+
+        self.output.texture().unwrap()
+    }
+}
@@ -0,0 +1,38 @@
+#version 330 core
+
+#include "oct_decode.glsl"
+
+#define MAX_LIGHTS 32
+
+// G-buffer, bound to texture units 0/1/2 by DeferredLightingPass
+uniform sampler2D u_gposition;
+uniform sampler2D u_gnormal;
+uniform sampler2D u_galbedo;
+
+uniform int u_light_count;
+uniform vec3 u_light_positions[MAX_LIGHTS];
+uniform vec3 u_light_colors[MAX_LIGHTS];
+uniform float u_light_radii[MAX_LIGHTS];
+
+in vec2 vUV;
+
+out vec4 FragColor;
+
+void main() {
+  vec3 position = texture(u_gposition, vUV).xyz;
+  vec3 normal = oct_decode(texture(u_gnormal, vUV).xy);
+  vec3 albedo = texture(u_galbedo, vUV).rgb;
+
+  vec3 color = vec3(0.0);
+  for (int i = 0; i < u_light_count; i++) {
+    vec3 to_light = u_light_positions[i] - position;
+    float dist = length(to_light);
+    float falloff = clamp(1.0 - dist / u_light_radii[i], 0.0, 1.0);
+    float diffuse = max(dot(normal, to_light / dist), 0.0);
+
+    color += albedo * u_light_colors[i] * diffuse * falloff * falloff;
+  }
+
+  // Unclamped, the HDR target keeps values above 1.0 for tone-mapping
+  FragColor = vec4(color, 1.0);
+}
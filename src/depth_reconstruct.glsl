@@ -0,0 +1,22 @@
+// View-space position from the depth buffer, the uniforms are set by DepthReconstruction::upload
+uniform float u_near;
+uniform float u_far;
+// (x, y) scale of the inverse projection
+uniform vec2 u_inv_proj_scale;
+// Third and fourth row of the inverse projection matrix
+uniform vec4 u_inv_proj_row2;
+uniform vec4 u_inv_proj_row3;
+
+// Distance along -Z in view space for a [0,1] depth buffer value
+float linearize_depth(float depth) {
+  float z_ndc = depth * 2.0 - 1.0;
+  return 2.0 * u_near * u_far / (u_far + u_near - z_ndc * (u_far - u_near));
+}
+
+vec3 reconstruct_position(vec2 uv, float depth) {
+  vec4 ndc = vec4(uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+  float view_z = dot(u_inv_proj_row2, ndc);
+  float w = dot(u_inv_proj_row3, ndc);
+
+  return vec3(ndc.xy * u_inv_proj_scale, view_z) / w;
+}
@@ -0,0 +1,333 @@
+use std::{error::Error, mem::size_of_val};
+
+use glam::Vec3;
+
+use crate::{
+    objects::{
+        BufferUsage, GlError, Ibo, IndexElement, IndirectBuffer, Primitive, Uniform, Vao, Vbo,
+        Vertex, Vertex3D,
+    },
+    render_state::GlStateCache,
+};
+
+/// Colored points, e.g: star catalog positions or simulation output
+pub struct PointCloud {
+    pub vao: Vao,
+    pub vbo: Vbo<Vertex3D>,
+    pub point_size: f32,
+    count: i32,
+}
+
+impl PointCloud {
+    /// Parses one `x,y,z,r,g,b` point per line, empty lines and lines starting with `#` are skipped
+    pub fn from_csv(path: &str) -> Result<PointCloud, Box<dyn Error>> {
+        let source = std::fs::read_to_string(path)?;
+
+        let mut points = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+
+            let [x, y, z, r, g, b] = values[..] else {
+                return Err(format!(
+                    "{}:{}: expected 6 values (x,y,z,r,g,b), found {}",
+                    path,
+                    number + 1,
+                    values.len()
+                )
+                .into());
+            };
+
+            points.push(Vertex3D::new(
+                (x, y, z),
+                (r, g, b),
+                (0.0, 0.0),
+                (0.0, 0.0, 0.0),
+            ));
+        }
+
+        Ok(Self::from_points(&points))
+    }
+
+    pub fn from_points(points: &[Vertex3D]) -> Self {
+        let mut vbo = Vbo::generate();
+        vbo.set(points);
+
+        let vao = Vao::generate();
+        vao.set_layout::<Vertex3D>();
+
+        PointCloud {
+            vao,
+            vbo,
+            point_size: 1.0,
+            count: points.len() as i32,
+        }
+    }
+
+    /// Draws all points, the size is written to the shader's `u_point_size` (use it for gl_PointSize)
+    pub fn draw(&self, u_point_size: &Uniform) {
+        u_point_size.set_1f(self.point_size);
+        self.vao.bind();
+
+        GlStateCache::enable(gl::PROGRAM_POINT_SIZE);
+        unsafe {
+            gl::DrawArrays(Primitive::Points.to_gl(), 0, self.count);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// Polyline through 3D points, e.g: photon trajectories around the blackhole.
+/// The color fades from `start_color` to `end_color` along the arc length
+pub struct LineStrip {
+    pub vao: Vao,
+    pub vbo: Vbo<Vertex3D>,
+    pub start_color: (f32, f32, f32),
+    pub end_color: (f32, f32, f32),
+    vertices: Vec<Vertex3D>,
+}
+
+impl LineStrip {
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        let mut strip = LineStrip {
+            vao: Vao::generate(),
+            vbo: Vbo::generate(),
+            start_color: (1.0, 1.0, 0.0),
+            end_color: (1.0, 0.0, 0.0),
+            vertices: Vec::new(),
+        };
+        strip.build_vertices(points);
+
+        strip.vbo.set(&strip.vertices);
+        strip.vao.set_layout::<Vertex3D>();
+        strip
+    }
+
+    /// Replaces the points, reuses the buffer if the point count didn't change
+    pub fn update_points(&mut self, new_points: &[[f32; 3]]) {
+        let old_len = self.vertices.len();
+        self.build_vertices(new_points);
+
+        if old_len == self.vertices.len() {
+            self.vbo.update_sub_data(0, &self.vertices);
+        } else {
+            self.vbo.set(&self.vertices);
+        }
+    }
+
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            gl::DrawArrays(Primitive::LineStrip.to_gl(), 0, self.vertices.len() as i32);
+        }
+    }
+
+    fn build_vertices(&mut self, points: &[[f32; 3]]) {
+        let mut arc_lengths = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, p) in points.iter().enumerate() {
+            if i > 0 {
+                total += Vec3::from(*p).distance(Vec3::from(points[i - 1]));
+            }
+            arc_lengths.push(total);
+        }
+
+        let (r0, g0, b0) = self.start_color;
+        let (r1, g1, b1) = self.end_color;
+        self.vertices = points
+            .iter()
+            .zip(arc_lengths)
+            .map(|(p, s)| {
+                let t = if total > 0.0 { s / total } else { 0.0 };
+                Vertex3D::new(
+                    (p[0], p[1], p[2]),
+                    (r0 + (r1 - r0) * t, g0 + (g1 - g0) * t, b0 + (b1 - b0) * t),
+                    (t, 0.0),
+                    (0.0, 0.0, 0.0),
+                )
+            })
+            .collect();
+    }
+}
+
+/// Flat disc in the XY plane (e.g: the event horizon) drawn as a triangle fan.
+/// UVs map the disc into [0,1]² with the center at (0.5, 0.5)
+pub struct CircleMesh {
+    pub vao: Vao,
+    pub vbo: Vbo<Vertex3D>,
+    segments: u32,
+}
+
+impl CircleMesh {
+    pub fn new(radius: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let color = (1.0, 1.0, 1.0);
+        let normal = (0.0, 0.0, 1.0);
+
+        let mut vertices = Vec::with_capacity(segments as usize + 2);
+        vertices.push(Vertex3D::new((0.0, 0.0, 0.0), color, (0.5, 0.5), normal));
+        // The first boundary vertex is repeated at the end to close the fan
+        for i in 0..=segments {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            vertices.push(Vertex3D::new(
+                (cos * radius, sin * radius, 0.0),
+                color,
+                (0.5 + 0.5 * cos, 0.5 + 0.5 * sin),
+                normal,
+            ));
+        }
+
+        let mut vbo = Vbo::generate();
+        vbo.set(&vertices);
+
+        let vao = Vao::generate();
+        vao.set_layout::<Vertex3D>();
+
+        CircleMesh { vao, vbo, segments }
+    }
+
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            gl::DrawArrays(Primitive::TriangleFan.to_gl(), 0, self.segments as i32 + 2);
+        }
+    }
+}
+
+/// Geometry that is uploaded once (BufferUsage::StaticDraw) and only drawn afterwards,
+/// e.g: the background quad or the blackhole sphere
+pub struct StaticMesh {
+    pub vao: Vao,
+    pub vbo: Vbo<Vertex3D>,
+    pub ibo: Ibo,
+    pub index_count: i32,
+}
+
+impl StaticMesh {
+    pub fn new(vertices: &[Vertex3D], indices: &[u32]) -> Self {
+        let mut vbo = Vbo::generate_with_usage(BufferUsage::StaticDraw);
+        vbo.set(vertices);
+
+        let vao = Vao::generate();
+        vao.set_layout::<Vertex3D>();
+
+        // Bound while the Vao is, so the Vao remembers it
+        let mut ibo = Ibo::generate_with_usage(BufferUsage::StaticDraw);
+        ibo.set(indices);
+
+        StaticMesh {
+            vao,
+            vbo,
+            ibo,
+            index_count: indices.len() as i32,
+        }
+    }
+
+    pub fn draw(&self) {
+        self.vao.bind();
+        self.ibo
+            .draw_elements(Primitive::Triangles, self.index_count);
+    }
+}
+
+/// Indexed `Vertex` geometry whose buffers can be updated in place, e.g: the fullscreen quad.
+/// Indices can be u32, u16 or u8, u16 halves the index buffer of meshes below 65536 vertices
+pub struct Mesh {
+    pub vao: Vao,
+    pub vbo: Vbo<Vertex>,
+    pub ibo: Ibo,
+    index_count: i32,
+}
+
+impl Mesh {
+    pub fn new<I: IndexElement>(vertices: &[Vertex], indices: &[I]) -> Result<Self, GlError> {
+        let mut vbo = Vbo::generate();
+        vbo.set(vertices);
+
+        let vao = Vao::generate();
+        vao.set();
+
+        // Bound while the Vao is, so the Vao remembers it
+        let mut ibo = Ibo::generate();
+        ibo.set(indices);
+        vao.unbind();
+
+        Ok(Mesh {
+            vao,
+            vbo,
+            ibo,
+            index_count: indices.len() as i32,
+        })
+    }
+
+    /// Overwrites the buffer in place if `vertices` fits, reallocates it otherwise
+    pub fn update_vertices(&mut self, vertices: &[Vertex]) {
+        if size_of_val(vertices) <= self.vbo.size() {
+            self.vbo.update_sub_data(0, vertices);
+        } else {
+            self.vbo.set(vertices);
+        }
+    }
+
+    /// Overwrites the buffer in place if `indices` fits and has the same index type,
+    /// reallocates it otherwise. Only the first `indices.len()` indices are drawn afterwards
+    pub fn update_indices<I: IndexElement>(&mut self, indices: &[I]) {
+        self.vao.bind();
+        if I::GL_TYPE == self.ibo.index_type() && size_of_val(indices) <= self.ibo.size() {
+            self.ibo.update_sub_data(0, indices);
+        } else {
+            self.ibo.set(indices);
+        }
+        self.vao.unbind();
+        self.index_count = indices.len() as i32;
+    }
+
+    /// Draws all indices as `mode` e.g: Primitive::Triangles
+    pub fn draw(&self, mode: Primitive) {
+        self.vao.bind();
+        self.ibo.draw_elements(mode, self.index_count);
+        self.vao.unbind();
+    }
+
+    /// Draws the mesh `count` times, per-instance attributes come from an InstanceVbo
+    /// attached with `instances.attach(&mesh.vao)`
+    pub fn draw_instanced(&self, mode: Primitive, count: u32) {
+        self.vao.bind();
+        self.ibo
+            .draw_elements_instanced(mode, self.index_count, count as i32);
+        self.vao.unbind();
+    }
+
+    /// Draws the first `count` commands of `buffer` in one call, each one picks its own
+    /// range of this mesh's indices (and instances). Needs an OpenGL 4.3 context
+    pub fn multi_draw_indirect(&self, mode: Primitive, buffer: &IndirectBuffer, count: usize) {
+        debug_assert!(
+            count <= buffer.len(),
+            "multi_draw_indirect draws {} of the {} commands in IndirectBuffer {}",
+            count,
+            buffer.len(),
+            buffer.id()
+        );
+        self.vao.bind();
+        buffer.bind();
+        self.ibo.multi_draw_elements_indirect(mode, count as i32);
+        buffer.unbind();
+        self.vao.unbind();
+    }
+}
@@ -0,0 +1,135 @@
+use std::{cell::Cell, ffi::CStr};
+
+use gl::types::{GLenum, GLint, GLuint};
+
+/// GL_EXT_texture_filter_anisotropic (core in OpenGL 4.6), the gl crate only has 4.5 enums
+pub const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+/// GL_ARB_gl_spirv (core in OpenGL 4.6)
+pub const SHADER_BINARY_FORMAT_SPIR_V: GLenum = 0x9551;
+
+thread_local! {
+    // GL contexts are current on one thread, so are their capabilities
+    static CAPS: Cell<Option<GpuCaps>> = const { Cell::new(None) };
+}
+
+/// What the current GL context reports about the driver
+#[derive(Clone, Debug)]
+pub struct GpuInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+    pub major: i32,
+    pub minor: i32,
+    /// e.g: "GL_EXT_texture_filter_anisotropic"
+    pub extensions: Vec<String>,
+}
+
+impl GpuInfo {
+    /// Needs a current GL context with the function pointers loaded
+    pub fn query() -> Self {
+        let mut major: GLint = 0;
+        let mut minor: GLint = 0;
+        let mut extension_count: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+        }
+        let extensions = (0..extension_count as GLuint)
+            .map(|index| {
+                let ptr = unsafe { gl::GetStringi(gl::EXTENSIONS, index) };
+                if ptr.is_null() {
+                    return String::new();
+                }
+                unsafe { CStr::from_ptr(ptr as *const _) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+
+        GpuInfo {
+            vendor: gl_string(gl::VENDOR),
+            renderer: gl_string(gl::RENDERER),
+            version: gl_string(gl::VERSION),
+            major,
+            minor,
+            extensions,
+        }
+    }
+
+    /// True if the context is at least OpenGL `major`.`minor`
+    pub fn supports_version(&self, major: i32, minor: i32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
+    /// GL_TEXTURE_CUBE_MAP_SEAMLESS is core since OpenGL 3.2
+    pub fn supports_seamless_cubemap(&self) -> bool {
+        self.supports_version(3, 2)
+    }
+
+    /// Core since OpenGL 4.6, an extension supported nearly everywhere before that
+    pub fn supports_anisotropic_filtering(&self) -> bool {
+        self.supports_version(4, 6)
+            || self.has_extension("GL_EXT_texture_filter_anisotropic")
+            || self.has_extension("GL_ARB_texture_filter_anisotropic")
+    }
+
+    /// SPIR-V shaders, see `Shader::spirv_from_bytes`
+    pub fn supports_spirv(&self) -> bool {
+        self.supports_version(4, 6) || self.has_extension("GL_ARB_gl_spirv")
+    }
+
+    /// Debug output, debug groups and object labels
+    pub fn supports_khr_debug(&self) -> bool {
+        self.supports_version(4, 3) || self.has_extension("GL_KHR_debug")
+    }
+}
+
+/// The `GpuInfo` checks made on hot paths (every frame or texture), queried once per context.
+/// A non-null function pointer from the loader doesn't prove support, GLX and EGL hand out
+/// stubs for entry points the driver doesn't implement
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuCaps {
+    pub anisotropic_filtering: bool,
+    pub khr_debug: bool,
+    pub spirv: bool,
+}
+
+impl GpuCaps {
+    /// Caches the capabilities in `info`, which has to come from the current context
+    pub fn store(info: &GpuInfo) -> Self {
+        let caps = GpuCaps {
+            anisotropic_filtering: info.supports_anisotropic_filtering(),
+            khr_debug: info.supports_khr_debug(),
+            spirv: info.supports_spirv(),
+        };
+        CAPS.set(Some(caps));
+        caps
+    }
+
+    /// Queries the current context and caches the result, call it whenever a new context
+    /// was made current. `Winsdl` does that
+    pub fn refresh() -> Self {
+        Self::store(&GpuInfo::query())
+    }
+
+    /// Cached by the last `refresh`, which is run on first use
+    pub fn current() -> Self {
+        CAPS.get().unwrap_or_else(Self::refresh)
+    }
+}
+
+fn gl_string(name: GLenum) -> String {
+    let ptr = unsafe { gl::GetString(name) };
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr as *const _) }
+        .to_string_lossy()
+        .into_owned()
+}
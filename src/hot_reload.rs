@@ -0,0 +1,65 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::objects::{Program, create_program_from_files};
+
+/// Recompiles a vertex/fragment program whenever one of its files is saved. Polls the
+/// modification times with fs::metadata(..) so no file watching dependency is needed
+pub struct ShaderWatcher {
+    vert: PathBuf,
+    frag: PathBuf,
+    /// Every watched file with its last seen modification time
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderWatcher {
+    pub fn new(vert: &Path, frag: &Path) -> Self {
+        let mut watcher = ShaderWatcher {
+            vert: vert.to_path_buf(),
+            frag: frag.to_path_buf(),
+            watched: Vec::new(),
+        };
+        watcher.watch(vert);
+        watcher.watch(frag);
+        watcher
+    }
+
+    /// Also recompiles when `path` changes, e.g: a file pulled in with `#include`
+    pub fn watch(&mut self, path: &Path) {
+        self.watched.push((path.to_path_buf(), modified(path)));
+    }
+
+    /// Call once per frame, returns the new Program if a watched file changed since the last
+    /// call. Compile errors are printed to stderr and give None, so the old Program stays
+    pub fn poll(&mut self) -> Option<Program> {
+        let mut changed = false;
+        for (path, last_modified) in &mut self.watched {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        if changed { self.reload() } else { None }
+    }
+
+    /// Recompiles right away whether or not anything changed, errors are handled like in `poll`
+    pub fn reload(&self) -> Option<Program> {
+        match create_program_from_files(&self.vert, &self.frag, None) {
+            Ok(program) => Some(program),
+            Err(e) => {
+                eprintln!("Shader reload failed, keeping the previous program: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// None while the file can't be read, e.g: editors that save by deleting and recreating it
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,28 @@
+use std::collections::HashSet;
+
+use sdl2::{EventPump, keyboard::Scancode};
+
+/// Keys held down this frame, for movement that lasts as long as a key is held instead of
+/// firing once per Event::KeyDown
+#[derive(Debug, Default)]
+pub struct InputState {
+    held: HashSet<Scancode>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call once per frame after the events were polled, the keyboard state is only
+    /// updated while polling
+    pub fn update(&mut self, event_pump: &EventPump) {
+        self.held.clear();
+        self.held
+            .extend(event_pump.keyboard_state().pressed_scancodes());
+    }
+
+    pub fn is_held(&self, scancode: Scancode) -> bool {
+        self.held.contains(&scancode)
+    }
+}
@@ -1,28 +1,101 @@
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+
 use sdl2::{
     event::{Event, WindowEvent},
     keyboard::Scancode,
 };
 
 use crate::{
-    objects::{Uniform, Vertex},
+    camera::Camera,
+    capture::{capture_framebuffer, capture_screenshot},
+    config::Config,
+    geometry::Mesh,
+    hot_reload::ShaderWatcher,
+    input::InputState,
+    objects::{Framebuffer, Primitive, Uniform, Vertex},
+    raymarch::RaymarchParams,
+    render_state::{ClearState, PolygonMode},
+    timer::{DeltaTimer, TimeController},
     winsdl::Winsdl,
 };
 
+pub mod bvh;
+pub mod camera;
+pub mod capture;
+pub mod config;
+pub mod debug;
+pub mod deferred;
+pub mod geometry;
+pub mod gpu_info;
+pub mod hot_reload;
+pub mod input;
+pub mod math;
+pub mod mipmap;
 pub mod objects;
+pub mod post_process;
+pub mod raymarch;
+pub mod recorder;
+pub mod render_state;
+pub mod shadow;
+#[cfg(test)]
+mod test_context;
+pub mod timer;
 mod winsdl;
 
+/// Camera rotation in radians per pixel of mouse motion
+const MOUSE_SENSITIVITY: f32 = 0.002;
+/// Camera movement in units per second while W/A/S/D is held
+const CAMERA_SPEED: f32 = 2.0;
+
 fn main() {
-    let mut winsdl = Winsdl::new(800, 800).unwrap();
-    unsafe {
-        gl::Viewport(0, 0, 800, 800);
-    }
+    let config = Config::default();
+    // `--offscreen <frames> [--out <path>]` renders that many frames into a Framebuffer of a
+    // hidden window, saves the last one as a PNG and exits, e.g: for CI screenshots
+    let offscreen = match parse_offscreen_args(std::env::args().skip(1)) {
+        Ok(offscreen) => offscreen,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let offscreen_frames = offscreen.as_ref().map(|offscreen| offscreen.frames);
+    let mut winsdl = match offscreen {
+        Some(_) => Winsdl::new_offscreen(config.width, config.height).unwrap(),
+        None => Winsdl::new(config.width as usize, config.height as usize).unwrap(),
+    };
+    // A hidden window's default framebuffer may not own its pixels, so render into this instead
+    let offscreen_target = offscreen.as_ref().map(|_| {
+        let (width, height) = winsdl.current_size();
+        Framebuffer::new(width, height, false).unwrap()
+    });
+    let clear_state = ClearState::from_config(&config);
 
     // Shader/Program stuff
     let mut program = objects::create_program().unwrap();
     program.set();
+    let mut shader_watcher = ShaderWatcher::new(
+        Path::new(objects::VERT_SHADER_PATH),
+        Path::new(objects::FRAG_SHADER_PATH),
+    );
     // Shader Uniform Locations
-    let u_resolution = Uniform::new(program.id(), "u_resolution").unwrap();
-    u_resolution.set_vec2f((800.0, 800.0));
+    let mut u_resolution = Uniform::new(program.id(), "u_resolution").unwrap();
+    u_resolution.set_vec2f(winsdl.current_size_f32());
+    // u_time is optional, the shader may not use it
+    let mut u_time = Uniform::try_new(program.id(), "u_time");
+    let mut raymarch = RaymarchParams::preview();
+    raymarch.upload(program.id());
+    // So are the camera matrices
+    let (width, height) = winsdl.current_size_f32();
+    let mut camera = Camera::new([0.0, 0.0, 5.0], width / height);
+    let mut u_view = Uniform::try_new(program.id(), "u_view");
+    let mut u_proj = Uniform::try_new(program.id(), "u_proj");
+    let mut mouse_captured = false;
+    let mut minimized = false;
+    let mut input = InputState::new();
+    let mut polygon_mode = PolygonMode::Fill;
 
     #[rustfmt::skip]
     let vertices = vec![
@@ -32,62 +105,244 @@ fn main() {
         Vertex::new((-1.0, 1.0,), (1.0,0.0,1.0), (0.0, 1.0)),
     ];
 
-    let indices = vec![0, 3, 1, 2, 3, 1];
-
-    let vbo = objects::Vbo::generate();
-    vbo.set(&vertices);
-
-    let vao = objects::Vao::generate();
-    vao.set();
+    let indices: Vec<u16> = vec![0, 3, 1, 2, 3, 1];
 
-    let ibo = objects::Ibo::generate();
-    ibo.set(&indices);
+    let quad = Mesh::new(&vertices, &indices).unwrap();
 
-    let mut time = 0.0;
+    let mut time = TimeController::new();
+    let mut delta_timer = DeltaTimer::new();
+    let mut frame: u32 = 0;
     'running: loop {
-        for event in winsdl.event_pump.poll_iter() {
+        if offscreen_frames.is_some_and(|frames| frame >= frames) {
+            break 'running;
+        }
+        frame += 1;
+
+        let mut reloaded = None;
+        let mut resized = None;
+        let mut toggle_fullscreen = false;
+        let mut screenshot = false;
+        let mut toggle_mouse_capture = false;
+        // Nothing is visible while minimized, sleep until something happens instead of rendering
+        let mut events = if minimized {
+            vec![winsdl.wait_event()]
+        } else {
+            Vec::new()
+        };
+        events.extend(winsdl.poll_events());
+        for event in events {
             match event {
                 Event::Window { win_event, .. } => match win_event {
-                    WindowEvent::Resized(width, height) => unsafe {
-                        gl::Viewport(0, 0, width, height);
-                        u_resolution.set_vec2f((width as f32, height as f32));
-                    },
+                    WindowEvent::Resized(width, height) => resized = Some((width, height)),
+                    WindowEvent::Minimized => minimized = true,
+                    WindowEvent::Restored | WindowEvent::Maximized => minimized = false,
                     _ => (),
                 },
                 Event::KeyDown { scancode, .. } => {
                     if let Some(scancode) = scancode {
                         match scancode {
-                            Scancode::R => {
-                                drop(program);
-                                program = objects::create_program().unwrap();
-                                program.set();
+                            Scancode::R => reloaded = shader_watcher.reload(),
+                            Scancode::F11 => toggle_fullscreen = true,
+                            Scancode::F12 => screenshot = true,
+                            Scancode::C => toggle_mouse_capture = true,
+                            Scancode::P => {
+                                polygon_mode = polygon_mode.next();
+                                polygon_mode.apply();
+                            }
+                            Scancode::Space => time.toggle_pause(),
+                            Scancode::LeftBracket => time.set_speed(time.speed * 0.5),
+                            Scancode::RightBracket => time.set_speed(time.speed * 2.0),
+                            Scancode::Equals | Scancode::KpPlus => {
+                                raymarch.increase_steps(8);
+                                raymarch.upload(program.id());
+                            }
+                            Scancode::Minus | Scancode::KpMinus => {
+                                raymarch.decrease_steps(8);
+                                raymarch.upload(program.id());
                             }
                             Scancode::Escape => break 'running,
                             _ => {}
                         }
                     }
                 }
+                Event::MouseMotion { xrel, yrel, .. } if mouse_captured => {
+                    camera.rotate(
+                        xrel as f32 * MOUSE_SENSITIVITY,
+                        -yrel as f32 * MOUSE_SENSITIVITY,
+                    );
+                }
                 Event::Quit { .. } => break 'running,
                 _ => {}
             }
         }
 
+        // Applied once all of this frame's events are handled
+        if let Some((width, height)) = resized {
+            winsdl.on_resize(width, height);
+        }
+        if toggle_fullscreen && let Err(e) = winsdl.toggle_fullscreen() {
+            eprintln!("Couldn't toggle fullscreen: {}", e);
+        }
+        if resized.is_some() || toggle_fullscreen {
+            let (width, height) = winsdl.current_size_f32();
+            u_resolution.set_vec2f((width, height));
+            // A minimized window is 0 pixels high, keep the last aspect until it's restored
+            if height > 0.0 {
+                camera.aspect = width / height;
+            }
+        }
+        input.update(&winsdl.event_pump);
+        if toggle_mouse_capture {
+            mouse_captured = !mouse_captured;
+            winsdl.capture_mouse(mouse_captured);
+        }
+
+        // Shaders are recompiled on R or whenever a .glsl file is saved
+        if let Some(new_program) = reloaded.or_else(|| shader_watcher.poll()) {
+            program = new_program;
+            program.set();
+            // The new program starts with all uniforms reset
+            if let Ok(uniform) = Uniform::new(program.id(), "u_resolution") {
+                u_resolution = uniform;
+                u_resolution.set_vec2f(winsdl.current_size_f32());
+            }
+            raymarch.upload(program.id());
+            u_time = Uniform::try_new(program.id(), "u_time");
+            u_view = Uniform::try_new(program.id(), "u_view");
+            u_proj = Uniform::try_new(program.id(), "u_proj");
+        }
+
         // Update Loop
-        time += 0.01;
+        // Offscreen frames keep a fixed step so their output doesn't depend on the machine
+        let delta = delta_timer.tick();
+        time.tick(if offscreen_frames.is_some() {
+            0.01
+        } else {
+            delta
+        });
+        if let Some(u_time) = &u_time {
+            u_time.set_1f(time.time);
+        }
+        // Once a second or so, setting the title every frame makes some window managers lag
+        if frame.is_multiple_of(60)
+            && let Err(e) = winsdl.set_title(&format!("OpenGL | {:.1} fps", delta_timer.fps()))
+        {
+            eprintln!("Couldn't set the window title: {}", e);
+        }
+        let step = CAMERA_SPEED * delta;
+        if input.is_held(Scancode::W) {
+            camera.move_forward(step);
+        }
+        if input.is_held(Scancode::S) {
+            camera.move_forward(-step);
+        }
+        if input.is_held(Scancode::D) {
+            camera.move_right(step);
+        }
+        if input.is_held(Scancode::A) {
+            camera.move_right(-step);
+        }
+        if let (Some(u_view), Some(u_proj)) = (&u_view, &u_proj) {
+            camera.upload(u_view, u_proj);
+        }
 
         // Render Loop
-        unsafe {
-            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+        if let Some(target) = &offscreen_target {
+            target.bind();
+        }
+        clear_state.apply(gl::COLOR_BUFFER_BIT);
+        quad.draw(Primitive::Triangles);
 
-            gl::DrawElements(
-                gl::TRIANGLES,
-                indices.len() as i32,
-                gl::UNSIGNED_INT,
-                std::ptr::null(),
-            );
+        if screenshot {
+            let (width, height) = winsdl.current_size();
+            let path = Path::new("./screenshot.png");
+            match capture_screenshot(width, height, path) {
+                Ok(()) => println!("Saved {}", path.display()),
+                Err(e) => eprintln!("Screenshot failed: {}", e),
+            }
         }
 
         winsdl.window.gl_swap_window();
     }
+
+    if let (Some(offscreen), Some(target)) = (&offscreen, &offscreen_target) {
+        match capture_framebuffer(target, &offscreen.out) {
+            Ok(()) => println!("Saved {}", offscreen.out.display()),
+            Err(e) => {
+                eprintln!("Offscreen capture failed: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// `--offscreen <frames> [--out <path>]` from the command line
+#[derive(Debug, PartialEq)]
+struct OffscreenArgs {
+    frames: u32,
+    out: PathBuf,
+}
+
+/// None without `--offscreen`, errors if its frame count is missing or not a number
+fn parse_offscreen_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<OffscreenArgs>, String> {
+    let mut frames = None;
+    let mut out = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--offscreen" => {
+                let count = args
+                    .next()
+                    .ok_or("--offscreen needs the number of frames to render")?;
+                frames = Some(count.parse::<u32>().map_err(|_| {
+                    format!("--offscreen needs a number of frames, got {:?}", count)
+                })?);
+            }
+            "--out" => out = Some(PathBuf::from(args.next().ok_or("--out needs a path")?)),
+            _ => {}
+        }
+    }
+
+    match (frames, out) {
+        (Some(frames), out) => Ok(Some(OffscreenArgs {
+            frames,
+            out: out.unwrap_or_else(|| PathBuf::from("./offscreen.png")),
+        })),
+        (None, Some(_)) => Err("--out only applies to --offscreen".to_string()),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<OffscreenArgs>, String> {
+        parse_offscreen_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn offscreen_args_parse() {
+        assert_eq!(parse(&[]), Ok(None));
+        assert_eq!(
+            parse(&["--offscreen", "3", "--out", "ci.png"]),
+            Ok(Some(OffscreenArgs {
+                frames: 3,
+                out: PathBuf::from("ci.png"),
+            }))
+        );
+        assert_eq!(
+            parse(&["--offscreen", "1"]).unwrap().unwrap().out,
+            PathBuf::from("./offscreen.png")
+        );
+    }
+
+    #[test]
+    fn offscreen_args_reject_a_missing_or_bad_frame_count() {
+        assert!(parse(&["--offscreen"]).is_err());
+        assert!(parse(&["--offscreen", "many"]).is_err());
+        assert!(parse(&["--offscreen", "--out", "ci.png"]).is_err());
+        assert!(parse(&["--out", "ci.png"]).is_err());
+    }
 }
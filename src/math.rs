@@ -0,0 +1,201 @@
+/// Two floats, e.g: a 2D position or a texture coordinate. Distinct from `Vec3` so a colour
+/// can't end up where a position was meant, `(x, y)` tuples convert into it
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Vec2 { x, y }
+    }
+}
+
+impl From<(f32, f32)> for Vec2 {
+    fn from((x, y): (f32, f32)) -> Self {
+        Vec2 { x, y }
+    }
+}
+
+impl From<[f32; 2]> for Vec2 {
+    fn from([x, y]: [f32; 2]) -> Self {
+        Vec2 { x, y }
+    }
+}
+
+/// Three floats, e.g: an RGB colour or a world space position, see `Vec2`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl From<(f32, f32, f32)> for Vec3 {
+    fn from((x, y, z): (f32, f32, f32)) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from([x, y, z]: [f32; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+/// Gram-Schmidt orthonormalization of a tangent frame, the normal is kept as the reference axis.
+/// Mirrors `orthonormalize_tbn` in `tbn.glsl`
+pub fn orthonormalize_tbn(t: [f32; 3], b: [f32; 3], n: [f32; 3]) -> ([f32; 3], [f32; 3], [f32; 3]) {
+    let n = glam::Vec3::from(n).normalize_or_zero();
+    let t = glam::Vec3::from(t);
+    let b = glam::Vec3::from(b);
+
+    let t = (t - n * n.dot(t)).normalize_or_zero();
+    let b = (b - n * n.dot(b) - t * t.dot(b)).normalize_or_zero();
+
+    (t.to_array(), b.to_array(), n.to_array())
+}
+
+/// GLSL `vec2 oct_encode(vec3 n)`, splice into a shader after the `#version` line
+pub const OCT_ENCODE_GLSL: &str = include_str!("oct_encode.glsl");
+/// GLSL `vec3 oct_decode(vec2 p)`, splice into a shader after the `#version` line
+pub const OCT_DECODE_GLSL: &str = include_str!("oct_decode.glsl");
+
+fn sign_not_zero(v: f32) -> f32 {
+    if v >= 0.0 { 1.0 } else { -1.0 }
+}
+
+/// Octahedral encoding of a unit normal into [-1,1]², fits a two channel (RG16F) target.
+/// The zero vector encodes as (0, 0) instead of NaN. Mirrors `oct_encode.glsl`
+pub fn oct_encode(n: [f32; 3]) -> (f32, f32) {
+    let l1 = n[0].abs() + n[1].abs() + n[2].abs();
+    if l1 == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (x, y) = (n[0] / l1, n[1] / l1);
+
+    if n[2] < 0.0 {
+        (
+            (1.0 - y.abs()) * sign_not_zero(x),
+            (1.0 - x.abs()) * sign_not_zero(y),
+        )
+    } else {
+        (x, y)
+    }
+}
+
+/// Inverse of `oct_encode`, mirrors `oct_decode.glsl`
+pub fn oct_decode(p: (f32, f32)) -> [f32; 3] {
+    let mut n = glam::Vec3::new(p.0, p.1, 1.0 - p.0.abs() - p.1.abs());
+    if n.z < 0.0 {
+        let (x, y) = (n.x, n.y);
+        n.x = (1.0 - y.abs()) * sign_not_zero(x);
+        n.y = (1.0 - x.abs()) * sign_not_zero(y);
+    }
+    n.normalize_or_zero().to_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn normalize(n: [f32; 3]) -> [f32; 3] {
+        glam::Vec3::from(n).normalize().to_array()
+    }
+
+    fn assert_round_trips(n: [f32; 3]) {
+        let decoded = oct_decode(oct_encode(n));
+        let error = (glam::Vec3::from(decoded) - glam::Vec3::from(n)).length();
+        assert!(
+            error <= EPSILON,
+            "{:?} decoded as {:?} (error {})",
+            n,
+            decoded,
+            error
+        );
+    }
+
+    #[test]
+    fn oct_round_trips_the_axes() {
+        for n in [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ] {
+            assert_round_trips(n);
+        }
+    }
+
+    #[test]
+    fn oct_round_trips_the_lower_hemisphere() {
+        for n in [
+            [0.3, 0.4, -0.866],
+            [-0.5, 0.5, -0.7],
+            [0.6, -0.2, -0.1],
+            [-0.1, -0.9, -0.3],
+        ] {
+            let n = normalize(n);
+            let (x, y) = oct_encode(n);
+            // Folded onto the outer triangles of the square
+            assert!(x.abs() + y.abs() >= 1.0 - EPSILON);
+            assert_round_trips(n);
+        }
+    }
+
+    #[test]
+    fn oct_round_trips_the_fold() {
+        for n in [
+            [0.6, 0.8, 0.0],
+            [-0.6, 0.8, 0.0],
+            [0.6, -0.8, 0.0],
+            [-0.6, -0.8, 0.0],
+        ] {
+            let (x, y) = oct_encode(n);
+            assert!((x.abs() + y.abs() - 1.0).abs() <= EPSILON);
+            assert_round_trips(n);
+        }
+    }
+
+    #[test]
+    fn oct_encodes_the_zero_vector_without_nan() {
+        assert_eq!(oct_encode([0.0, 0.0, 0.0]), (0.0, 0.0));
+        assert_eq!(oct_encode([0.0, 0.0, -0.0]), (0.0, 0.0));
+        // Decodes as +Z like any other (0, 0)
+        assert_eq!(oct_decode(oct_encode([0.0, 0.0, 0.0])), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn oct_round_trips_a_sphere_sweep() {
+        let steps = 64;
+        for i in 0..=steps {
+            let theta = std::f32::consts::PI * i as f32 / steps as f32;
+            for j in 0..steps * 2 {
+                let phi = std::f32::consts::PI * j as f32 / steps as f32;
+                let n = [
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ];
+                assert_round_trips(normalize(n));
+            }
+        }
+    }
+}
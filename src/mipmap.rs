@@ -0,0 +1,193 @@
+use std::{error::Error, f32::consts::PI};
+
+use gl::types::GLint;
+
+use crate::{
+    objects::{ComputeProgram, InternalFormat, Texture2D, Uniform},
+    render_state::ProgramGuard,
+};
+
+/// Has to match MAX_TAPS in mipmap_comp.glsl
+pub const MAX_MIP_TAPS: usize = 6;
+
+/// Downsampling kernel of the ComputeMipmapGenerator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// 2x2 average, what gl::GenerateMipmap usually does
+    Box,
+    /// 4x4 bilinear tent
+    Tent,
+    /// 12x12 Lanczos-3, sharpest but may ring around very bright HDR texels
+    Lanczos3,
+}
+
+impl MipFilter {
+    /// Normalized one-sided weights, `weights()[i]` is for the source texels at distance
+    /// i + 0.5 from the destination texel center (in source texels)
+    pub fn weights(self) -> Vec<f32> {
+        let kernel = |x: f32| match self {
+            MipFilter::Box => 1.0,
+            MipFilter::Tent => (1.0 - x / 2.0).max(0.0),
+            MipFilter::Lanczos3 => lanczos3(x / 2.0),
+        };
+        let taps = match self {
+            MipFilter::Box => 1,
+            MipFilter::Tent => 2,
+            MipFilter::Lanczos3 => MAX_MIP_TAPS,
+        };
+
+        let mut weights: Vec<f32> = (0..taps).map(|i| kernel(i as f32 + 0.5)).collect();
+        let total = 2.0 * weights.iter().sum::<f32>();
+        weights.iter_mut().for_each(|w| *w /= total);
+        weights
+    }
+}
+
+fn lanczos3(x: f32) -> f32 {
+    let sinc = |x: f32| {
+        if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    };
+    if x.abs() < 3.0 {
+        sinc(x) * sinc(x / 3.0)
+    } else {
+        0.0
+    }
+}
+
+/// GLSL image format qualifier, None for formats that can't be bound as an image
+fn image_format(format: InternalFormat) -> Option<&'static str> {
+    match format {
+        InternalFormat::Rgba8 => Some("rgba8"),
+        InternalFormat::Rgba16F => Some("rgba16f"),
+        InternalFormat::Rgba32F => Some("rgba32f"),
+        InternalFormat::Rg16F => Some("rg16f"),
+        InternalFormat::R32F => Some("r32f"),
+        InternalFormat::Depth24 => None,
+    }
+}
+
+/// Builds the mip chain of a texture with a compute shader, as alternative to the driver
+/// defined filtering of gl::GenerateMipmap(..). Needs an OpenGL 4.3 context
+pub struct ComputeMipmapGenerator {
+    pub filter: MipFilter,
+    format: InternalFormat,
+    program: ComputeProgram,
+    u_taps: Uniform,
+    u_weights: Uniform,
+}
+
+impl ComputeMipmapGenerator {
+    /// The shader is compiled for textures of `format`
+    pub fn new(filter: MipFilter, format: InternalFormat) -> Result<Self, Box<dyn Error>> {
+        let qualifier = image_format(format)
+            .ok_or_else(|| format!("{:?} can't be used as an image format", format))?;
+        let source = include_str!("mipmap_comp.glsl").replacen(
+            "\n",
+            &format!("\n#define IMAGE_FORMAT {}\n", qualifier),
+            1,
+        );
+        let program = ComputeProgram::from_source(&source)?;
+
+        Ok(ComputeMipmapGenerator {
+            filter,
+            format,
+            u_taps: Uniform::new(program.id(), "u_taps")?,
+            u_weights: Uniform::new(program.id(), "u_weights")?,
+            program,
+        })
+    }
+
+    /// Allocates and fills mip levels 1..levels of `tex` from level 0, `levels` is clamped
+    /// to the full chain
+    pub fn generate(&self, tex: &mut Texture2D, levels: u32) -> Result<(), String> {
+        if tex.format() != self.format {
+            return Err(format!(
+                "Texture {} is {:?} but the generator was compiled for {:?}",
+                tex.id(),
+                tex.format(),
+                self.format
+            ));
+        }
+
+        let (width, height) = tex.size();
+        let levels = levels.min(32 - width.max(height).max(1).leading_zeros());
+        let level_size = |level: u32| ((width >> level).max(1), (height >> level).max(1));
+        let (internal, pixel_format, pixel_type) = self.format.to_gl();
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, tex.id());
+            for level in 1..levels {
+                let (w, h) = level_size(level);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    level as GLint,
+                    internal as GLint,
+                    w as GLint,
+                    h as GLint,
+                    0,
+                    pixel_format,
+                    pixel_type,
+                    std::ptr::null(),
+                );
+            }
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAX_LEVEL,
+                levels.saturating_sub(1) as GLint,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_LINEAR as GLint,
+            );
+        }
+
+        let _guard = ProgramGuard::save();
+        self.program.program().set();
+
+        let weights = self.filter.weights();
+        self.u_taps.set_1i(weights.len() as i32);
+        self.u_weights.set_1fv(&weights);
+
+        for level in 1..levels {
+            let (w, h) = level_size(level);
+            unsafe {
+                gl::BindImageTexture(
+                    0,
+                    tex.id(),
+                    (level - 1) as GLint,
+                    gl::FALSE,
+                    0,
+                    gl::READ_ONLY,
+                    internal,
+                );
+                gl::BindImageTexture(
+                    1,
+                    tex.id(),
+                    level as GLint,
+                    gl::FALSE,
+                    0,
+                    gl::WRITE_ONLY,
+                    internal,
+                );
+            }
+            // The next level reads what this dispatch wrote
+            self.program.dispatch_with_barrier(
+                w.div_ceil(8),
+                h.div_ceil(8),
+                1,
+                gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+            );
+        }
+
+        unsafe {
+            gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+        }
+        tex.set_mipmapped(levels > 1);
+        Ok(())
+    }
+}
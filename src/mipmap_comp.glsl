@@ -0,0 +1,38 @@
+#version 430 core
+
+// IMAGE_FORMAT (e.g: rgba16f) is defined by ComputeMipmapGenerator before compiling
+
+#define MAX_TAPS 6
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(IMAGE_FORMAT, binding = 0) uniform readonly image2D u_src;
+layout(IMAGE_FORMAT, binding = 1) uniform writeonly image2D u_dst;
+
+// One-sided separable kernel, u_weights[i] belongs to the source texels at distance i + 0.5
+// from the destination texel center
+uniform int u_taps;
+uniform float u_weights[MAX_TAPS];
+
+void main() {
+  ivec2 dst = ivec2(gl_GlobalInvocationID.xy);
+  if (any(greaterThanEqual(dst, imageSize(u_dst)))) {
+    return;
+  }
+
+  ivec2 src_max = imageSize(u_src) - 1;
+  // Source texels 2 * dst and 2 * dst + 1 straddle the destination center
+  ivec2 base = dst * 2;
+
+  vec4 sum = vec4(0.0);
+  for (int y = -u_taps; y < u_taps; y++) {
+    float wy = u_weights[y < 0 ? -y - 1 : y];
+    for (int x = -u_taps; x < u_taps; x++) {
+      float wx = u_weights[x < 0 ? -x - 1 : x];
+      ivec2 p = clamp(base + ivec2(x, y) + 1, ivec2(0), src_max);
+      sum += imageLoad(u_src, p) * wx * wy;
+    }
+  }
+
+  imageStore(u_dst, dst, sum);
+}
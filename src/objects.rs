@@ -1,11 +1,175 @@
+pub mod mesh;
+
 use std::{
     error::Error,
-    ffi::{CStr, CString},
+    ffi::{CStr, CString, NulError, c_void},
+    fmt, io,
+    marker::PhantomData,
     mem::{offset_of, size_of, size_of_val},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
     ptr::{null, null_mut},
+    sync::{
+        Mutex,
+        atomic::{AtomicPtr, Ordering},
+    },
+};
+
+use gl::types::{
+    GLbitfield, GLboolean, GLchar, GLenum, GLint, GLintptr, GLsizei, GLsizeiptr, GLsync, GLuint,
+    GLvoid,
+};
+
+use crate::{
+    gpu_info::{
+        GpuCaps, MAX_TEXTURE_MAX_ANISOTROPY, SHADER_BINARY_FORMAT_SPIR_V, TEXTURE_MAX_ANISOTROPY,
+    },
+    math::{Vec2, Vec3},
+    render_state::{GlStateCache, ProgramGuard, with_pixel_store},
 };
 
-use gl::types::{GLchar, GLenum, GLint, GLsizeiptr, GLuint, GLvoid};
+/// Everything the GL wrappers can fail with
+#[derive(Debug)]
+pub enum GlError {
+    /// `kind` is e.g: gl::FRAGMENT_SHADER, `log` the driver's info log
+    ShaderCompile {
+        kind: GLenum,
+        log: String,
+    },
+    ProgramLink {
+        log: String,
+    },
+    /// The program can't run with the current GL state, see `Program::validate`
+    ProgramValidation {
+        log: String,
+    },
+    /// A ProgramBuilder was built without a stage it needs, `kind` is e.g: gl::VERTEX_SHADER
+    MissingStage {
+        kind: GLenum,
+    },
+    /// The uniform isn't declared or was optimized out because it's unused
+    UniformNotFound {
+        name: String,
+    },
+    /// `status` is the gl::CheckFramebufferStatus(..) result
+    FramebufferIncomplete {
+        id: GLuint,
+        status: GLenum,
+    },
+    /// Attributes missing or mismatched between a Vao and a Program, see `validate_pipeline`
+    PipelineMismatch(Vec<String>),
+    /// `#include`s that lead back to a file already being included, the last one repeats
+    IncludeCycle(Vec<PathBuf>),
+    /// gl::MapBufferRange(..) returned null, e.g: the buffer is empty or already mapped
+    BufferMapFailed {
+        id: GLuint,
+    },
+    /// The driver lacks the OpenGL version or extension, e.g: "GL_ARB_gl_spirv"
+    Unsupported {
+        feature: &'static str,
+    },
+    IoError(io::Error),
+    NulError(NulError),
+}
+
+impl fmt::Display for GlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlError::ShaderCompile { kind, log } => {
+                write!(f, "{} shader failed to compile: {}", stage_name(*kind), log)
+            }
+            GlError::MissingStage { kind } => {
+                write!(
+                    f,
+                    "Program has no {} shader",
+                    stage_name(*kind).to_lowercase()
+                )
+            }
+            GlError::ProgramLink { log } => write!(f, "Program failed to link: {}", log),
+            GlError::ProgramValidation { log } => {
+                write!(f, "Program failed to validate: {}", log)
+            }
+            GlError::UniformNotFound { name } => {
+                write!(f, "Couldn't get Uniform location for {}", name)
+            }
+            GlError::FramebufferIncomplete { id, status } => {
+                write!(
+                    f,
+                    "Framebuffer {} is incomplete (status 0x{:X})",
+                    id, status
+                )
+            }
+            GlError::PipelineMismatch(errors) => write!(f, "{}", errors.join("\n")),
+            GlError::IncludeCycle(chain) => {
+                let chain: Vec<_> = chain
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(f, "#include cycle: {}", chain.join(" -> "))
+            }
+            GlError::BufferMapFailed { id } => write!(f, "Couldn't map buffer {}", id),
+            GlError::Unsupported { feature } => write!(f, "{} isn't supported", feature),
+            GlError::IoError(e) => write!(f, "{}", e),
+            GlError::NulError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn stage_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::VERTEX_SHADER => "Vertex",
+        gl::FRAGMENT_SHADER => "Fragment",
+        gl::GEOMETRY_SHADER => "Geometry",
+        gl::TESS_CONTROL_SHADER => "Tessellation control",
+        gl::TESS_EVALUATION_SHADER => "Tessellation evaluation",
+        gl::COMPUTE_SHADER => "Compute",
+        _ => "Unknown",
+    }
+}
+
+impl Error for GlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GlError::IoError(e) => Some(e),
+            GlError::NulError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GlError {
+    fn from(e: io::Error) -> Self {
+        GlError::IoError(e)
+    }
+}
+
+impl From<NulError> for GlError {
+    fn from(e: NulError) -> Self {
+        GlError::NulError(e)
+    }
+}
+
+/// glSpecializeShader(shader, entry_point, constant_count, constant_indices, constant_values),
+/// OpenGL 4.6 so the gl crate doesn't load it
+type SpecializeShaderFn =
+    unsafe extern "system" fn(GLuint, *const GLchar, GLuint, *const GLuint, *const GLuint);
+
+static SPECIALIZE_SHADER: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+
+/// Loads the GL functions `Shader::spirv_from_bytes` needs with the same `loader` as
+/// `gl::load_with(..)`, they stay null if the driver has neither 4.6 nor GL_ARB_gl_spirv.
+/// Needs the context current, the loader alone can't tell, see `GpuCaps`
+pub fn load_spirv_with(mut loader: impl FnMut(&'static str) -> *const c_void) {
+    if !GpuCaps::current().spirv {
+        SPECIALIZE_SHADER.store(null_mut(), Ordering::Release);
+        return;
+    }
+    let mut ptr = loader("glSpecializeShader");
+    if ptr.is_null() {
+        ptr = loader("glSpecializeShaderARB");
+    }
+    SPECIALIZE_SHADER.store(ptr as *mut c_void, Ordering::Release);
+}
 
 /// OpenGL Shader (Rendering Pipeline)
 pub struct Shader {
@@ -13,13 +177,53 @@ pub struct Shader {
 }
 
 impl Shader {
-    pub fn from_source(source: &CStr, kind: GLenum) -> Result<Self, String> {
+    pub fn from_source(source: &CStr, kind: GLenum) -> Result<Self, GlError> {
         let id = unsafe { gl::CreateShader(kind) };
         unsafe {
             gl::ShaderSource(id, 1, &source.as_ptr(), null());
             gl::CompileShader(id);
         }
 
+        Self::check_compile_status(id, kind)?;
+        Ok(Shader { id })
+    }
+
+    /// Loads a SPIR-V module, e.g: compiled with `glslangValidator -G` or `shaderc`, and
+    /// specializes its `entry_point`. Needs OpenGL 4.6 or GL_ARB_gl_spirv and
+    /// `load_spirv_with` to have been called, `Winsdl::new` does that
+    pub fn spirv_from_bytes(
+        binary: &[u8],
+        kind: GLenum,
+        entry_point: &str,
+    ) -> Result<Self, GlError> {
+        let specialize = SPECIALIZE_SHADER.load(Ordering::Acquire);
+        if specialize.is_null() {
+            return Err(GlError::Unsupported {
+                feature: "GL_ARB_gl_spirv",
+            });
+        }
+        // SAFETY: only ever set by `load_spirv_with` to the driver's glSpecializeShader
+        let specialize: SpecializeShaderFn = unsafe { std::mem::transmute(specialize) };
+        let entry_point = CString::new(entry_point)?;
+
+        let id = unsafe { gl::CreateShader(kind) };
+        unsafe {
+            gl::ShaderBinary(
+                1,
+                &id,
+                SHADER_BINARY_FORMAT_SPIR_V,
+                binary.as_ptr() as *const GLvoid,
+                binary.len() as GLsizei,
+            );
+            specialize(id, entry_point.as_ptr(), 0, null(), null());
+        }
+
+        Self::check_compile_status(id, kind)?;
+        Ok(Shader { id })
+    }
+
+    /// Deletes the shader `id` and returns its info log if it didn't compile
+    fn check_compile_status(id: GLuint, kind: GLenum) -> Result<(), GlError> {
         let mut success: GLint = 1;
         unsafe { gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut success) };
 
@@ -36,10 +240,28 @@ impl Shader {
                 gl::DeleteShader(id);
             }
 
-            return Err(error.to_string_lossy().into_owned());
+            return Err(GlError::ShaderCompile {
+                kind,
+                log: error.to_string_lossy().into_owned(),
+            });
         }
 
-        Ok(Shader { id })
+        Ok(())
+    }
+
+    /// Like `from_source` for a plain `&str`, e.g: `include_str!(..)` or inline GLSL
+    pub fn from_source_str(source: &str, kind: GLenum) -> Result<Self, GlError> {
+        Self::from_source(&CString::new(source)?, kind)
+    }
+
+    /// Shorthand for `from_source_str(source, gl::GEOMETRY_SHADER)`
+    pub fn geometry_from_source_str(source: &str) -> Result<Self, GlError> {
+        Self::from_source_str(source, gl::GEOMETRY_SHADER)
+    }
+
+    /// Shorthand for `from_source_str(source, gl::COMPUTE_SHADER)`
+    pub fn compute_from_source_str(source: &str) -> Result<Self, GlError> {
+        Self::from_source_str(source, gl::COMPUTE_SHADER)
     }
 
     pub fn id(&self) -> GLuint {
@@ -60,19 +282,60 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn from_shaders(shaders: &[Shader]) -> Result<Self, String> {
+    pub fn from_shaders(shaders: &[Shader]) -> Result<Self, GlError> {
+        Self::link(shaders, &[], None, false)
+    }
+
+    /// `attrib_locations` are bound with gl::BindAttribLocation(..) before linking,
+    /// `layout (location=N)` qualifiers in the shader still take precedence.
+    /// `feedback` is the transform feedback (varyings, buffer mode) if any.
+    /// `retrievable` hints the driver that `binary_save` will be called on the program
+    fn link(
+        shaders: &[Shader],
+        attrib_locations: &[(CString, GLuint)],
+        feedback: Option<(&[CString], GLenum)>,
+        retrievable: bool,
+    ) -> Result<Self, GlError> {
         let id = unsafe { gl::CreateProgram() };
 
+        if retrievable {
+            unsafe {
+                gl::ProgramParameteri(id, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint);
+            }
+        }
+
         for shader in shaders {
             unsafe {
                 gl::AttachShader(id, shader.id());
             }
         }
+        for (name, index) in attrib_locations {
+            unsafe {
+                gl::BindAttribLocation(id, *index, name.as_ptr());
+            }
+        }
+        if let Some((varyings, buffer_mode)) = feedback {
+            let names: Vec<*const GLchar> = varyings.iter().map(|name| name.as_ptr()).collect();
+            unsafe {
+                gl::TransformFeedbackVaryings(
+                    id,
+                    names.len() as GLsizei,
+                    names.as_ptr(),
+                    buffer_mode,
+                );
+            }
+        }
 
         unsafe {
             gl::LinkProgram(id);
         }
 
+        Self::check_link_status(id)?;
+        Ok(Program { id })
+    }
+
+    /// Deletes the program `id` and returns its info log if it isn't linked
+    fn check_link_status(id: GLuint) -> Result<(), GlError> {
         let mut success: GLint = 1;
         unsafe { gl::GetProgramiv(id, gl::LINK_STATUS, &mut success) };
 
@@ -89,9 +352,71 @@ impl Program {
                 gl::DeleteProgram(id);
             }
 
-            return Err(error.to_string_lossy().into_owned());
+            return Err(GlError::ProgramLink {
+                log: error.to_string_lossy().into_owned(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Writes the linked program as returned by gl::GetProgramBinary(..): the binary format
+    /// as little endian u32 followed by the driver's bytes
+    pub fn binary_save(&self, path: &Path) -> Result<(), GlError> {
+        let mut len: GLint = 0;
+        unsafe {
+            gl::GetProgramiv(self.id, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        }
+
+        let mut binary = vec![0u8; len.max(0) as usize];
+        let mut written: GLsizei = 0;
+        let mut format: GLenum = 0;
+        unsafe {
+            gl::GetProgramBinary(
+                self.id,
+                len,
+                &mut written,
+                &mut format,
+                binary.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+        binary.truncate(written.max(0) as usize);
+        if binary.is_empty() {
+            return Err(GlError::IoError(io::Error::other(format!(
+                "Program {} has no binary to save, the driver may not support any binary format",
+                self.id
+            ))));
+        }
+
+        let mut file = format.to_le_bytes().to_vec();
+        file.extend_from_slice(&binary);
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+
+    /// Loads a program written by `binary_save`. Binaries only work with the driver (and
+    /// version) that saved them, otherwise this fails with GlError::ProgramLink, see
+    /// `create_program_cached` for falling back to the sources
+    pub fn from_binary(path: &Path) -> Result<Self, GlError> {
+        let file = std::fs::read(path)?;
+        let Some((format, binary)) = file.split_first_chunk::<4>() else {
+            return Err(GlError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: too short for a program binary", path.display()),
+            )));
+        };
+
+        let id = unsafe { gl::CreateProgram() };
+        unsafe {
+            gl::ProgramBinary(
+                id,
+                GLenum::from_le_bytes(*format),
+                binary.as_ptr() as *const GLvoid,
+                binary.len() as GLsizei,
+            );
         }
 
+        Self::check_link_status(id)?;
         Ok(Program { id })
     }
 
@@ -99,11 +424,122 @@ impl Program {
         self.id
     }
 
+    /// Checks whether the program can run with the current GL state (sampler units, bound
+    /// buffers, ..), call it after the uniforms are set up and before drawing. Slow, debug only
+    pub fn validate(&self) -> Result<(), GlError> {
+        let mut success: GLint = 1;
+        unsafe {
+            gl::ValidateProgram(self.id);
+            gl::GetProgramiv(self.id, gl::VALIDATE_STATUS, &mut success);
+        }
+        if success != 0 {
+            return Ok(());
+        }
+
+        let mut len: GLint = 0;
+        unsafe {
+            gl::GetProgramiv(self.id, gl::INFO_LOG_LENGTH, &mut len);
+        }
+
+        let error = create_whitespace_cstring_with_len(len as usize);
+        unsafe {
+            gl::GetProgramInfoLog(self.id, len, null_mut(), error.as_ptr() as *mut GLchar);
+        }
+
+        Err(GlError::ProgramValidation {
+            log: error.to_string_lossy().into_owned(),
+        })
+    }
+
     /// This sets the (Shader)Program as the current one e.g: gl::UseProgram(..)
     pub fn set(&self) {
+        GlStateCache::use_program(self.id);
+    }
+
+    /// Lists the vertex attributes the linked program actually reads
+    pub fn active_attributes(&self) -> Vec<AttributeInfo> {
+        let mut count: GLint = 0;
+        let mut max_len: GLint = 0;
         unsafe {
-            gl::UseProgram(self.id);
+            gl::GetProgramiv(self.id, gl::ACTIVE_ATTRIBUTES, &mut count);
+            gl::GetProgramiv(self.id, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_len);
         }
+
+        (0..count as GLuint)
+            .map(|index| {
+                let mut name_buf: Vec<u8> = vec![0; max_len.max(1) as usize];
+                let mut len: GLsizei = 0;
+                let mut size: GLint = 0;
+                let mut gl_type: GLenum = 0;
+                unsafe {
+                    gl::GetActiveAttrib(
+                        self.id,
+                        index,
+                        max_len,
+                        &mut len,
+                        &mut size,
+                        &mut gl_type,
+                        name_buf.as_mut_ptr() as *mut GLchar,
+                    );
+                }
+                name_buf.truncate(len as usize);
+                let name = String::from_utf8_lossy(&name_buf).into_owned();
+
+                let cname = CString::new(name.as_str()).expect("attribute names contain no NUL");
+                let location = unsafe { gl::GetAttribLocation(self.id, cname.as_ptr()) };
+
+                AttributeInfo {
+                    name,
+                    location,
+                    gl_type,
+                    size,
+                }
+            })
+            .collect()
+    }
+
+    /// Lists the uniforms the linked program actually uses, uniforms the compiler optimized
+    /// out are missing. Arrays show up once with their element count as `size`
+    pub fn introspect_uniforms(&self) -> Vec<UniformInfo> {
+        let mut count: GLint = 0;
+        let mut max_len: GLint = 0;
+        unsafe {
+            gl::GetProgramiv(self.id, gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(self.id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+        }
+
+        (0..count as GLuint)
+            .map(|index| {
+                let mut name_buf: Vec<u8> = vec![0; max_len.max(1) as usize];
+                let mut len: GLsizei = 0;
+                let mut size: GLint = 0;
+                let mut gl_type: GLenum = 0;
+                unsafe {
+                    gl::GetActiveUniform(
+                        self.id,
+                        index,
+                        max_len,
+                        &mut len,
+                        &mut size,
+                        &mut gl_type,
+                        name_buf.as_mut_ptr() as *mut GLchar,
+                    );
+                }
+                name_buf.truncate(len as usize);
+                let name = String::from_utf8_lossy(&name_buf).into_owned();
+
+                // Uniform block members have no location and give -1
+                let cname = CString::new(name.as_str()).expect("uniform names contain no NUL");
+                let location = unsafe { gl::GetUniformLocation(self.id, cname.as_ptr()) };
+
+                UniformInfo {
+                    name,
+                    gl_type,
+                    size,
+                    location,
+                }
+            })
+            .collect()
     }
 }
 
@@ -115,45 +551,622 @@ impl Drop for Program {
     }
 }
 
+/// Program made of a single compute shader, e.g: integrating geodesics into a
+/// ShaderStorageBuffer. Needs an OpenGL 4.3 context
+pub struct ComputeProgram {
+    program: Program,
+}
+
+impl ComputeProgram {
+    pub fn from_source(source: &str) -> Result<Self, GlError> {
+        let shader = Shader::compute_from_source_str(source)?;
+        Ok(ComputeProgram {
+            program: Program::from_shaders(&[shader])?,
+        })
+    }
+
+    /// Runs `x * y * z` work groups and waits until their shader storage writes are visible
+    /// to later commands
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) {
+        self.dispatch_with_barrier(x, y, z, gl::SHADER_STORAGE_BARRIER_BIT);
+    }
+
+    /// `dispatch` for shaders that write something else, `barriers` is passed to
+    /// gl::MemoryBarrier(..), e.g: gl::SHADER_IMAGE_ACCESS_BARRIER_BIT after image stores
+    pub fn dispatch_with_barrier(&self, x: u32, y: u32, z: u32, barriers: GLbitfield) {
+        let _guard = ProgramGuard::save();
+        self.program.set();
+        unsafe {
+            gl::DispatchCompute(x, y, z);
+            gl::MemoryBarrier(barriers);
+        }
+    }
+
+    /// The wrapped Program, e.g: for looking up uniforms
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.program.id()
+    }
+}
+
+/// Compiles every stage as soon as it's attached and keeps going on errors, so `build`
+/// reports all broken stages at once. A vertex shader is required (unless it's a compute
+/// shader on its own), geometry and tessellation stages are optional, tessellation needs an
+/// OpenGL 4.0 context
+/// e.g: `ProgramBuilder::new().attach_vert(vert).attach_frag(frag).build()`
+#[derive(Default)]
+pub struct ProgramBuilder {
+    shaders: Vec<Shader>,
+    /// Kinds of every attached stage, including the ones that failed to compile
+    stages: Vec<GLenum>,
+    attrib_locations: Vec<(CString, GLuint)>,
+    feedback_varyings: Vec<CString>,
+    feedback_mode: GLenum,
+    errors: Vec<GlError>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attach_vert(self, source: &str) -> Self {
+        self.attach(source, gl::VERTEX_SHADER)
+    }
+
+    pub fn attach_frag(self, source: &str) -> Self {
+        self.attach(source, gl::FRAGMENT_SHADER)
+    }
+
+    pub fn attach_geom(self, source: &str) -> Self {
+        self.attach(source, gl::GEOMETRY_SHADER)
+    }
+
+    /// Tessellation control shader
+    pub fn attach_tesc(self, source: &str) -> Self {
+        self.attach(source, gl::TESS_CONTROL_SHADER)
+    }
+
+    /// Tessellation evaluation shader
+    pub fn attach_tese(self, source: &str) -> Self {
+        self.attach(source, gl::TESS_EVALUATION_SHADER)
+    }
+
+    /// Any other stage, `kind` is e.g: gl::VERTEX_SHADER
+    pub fn attach(mut self, source: &str, kind: GLenum) -> Self {
+        self.stages.push(kind);
+        match Shader::from_source_str(source, kind) {
+            Ok(shader) => self.shaders.push(shader),
+            Err(e) => self.errors.push(e),
+        }
+        self
+    }
+
+    /// Puts attribute `name` at location `index` for drivers that don't honor
+    /// `layout (location=N)`, e.g: older Intel integrated graphics
+    pub fn bind_attrib(mut self, name: &str, index: u32) -> Self {
+        match CString::new(name) {
+            Ok(name) => self.attrib_locations.push((name, index)),
+            Err(e) => self.errors.push(e.into()),
+        }
+        self
+    }
+
+    /// Captures the vertex (or geometry) shader outputs `names` while a TransformFeedback is
+    /// active. `interleaved` writes them back to back into binding 0, e.g: a whole Vertex3D,
+    /// otherwise output i goes to binding i
+    pub fn set_transform_feedback_varyings(mut self, names: &[&str], interleaved: bool) -> Self {
+        for name in names {
+            match CString::new(*name) {
+                Ok(name) => self.feedback_varyings.push(name),
+                Err(e) => self.errors.push(e.into()),
+            }
+        }
+        self.feedback_mode = if interleaved {
+            gl::INTERLEAVED_ATTRIBS
+        } else {
+            gl::SEPARATE_ATTRIBS
+        };
+        self
+    }
+
+    /// Links the attached stages, or returns every compile error (and a missing vertex
+    /// shader) without linking
+    pub fn build(mut self) -> Result<Program, Vec<GlError>> {
+        if !self.stages.contains(&gl::VERTEX_SHADER) && !self.stages.contains(&gl::COMPUTE_SHADER) {
+            self.errors.push(GlError::MissingStage {
+                kind: gl::VERTEX_SHADER,
+            });
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        let feedback = (!self.feedback_varyings.is_empty())
+            .then_some((self.feedback_varyings.as_slice(), self.feedback_mode));
+        Program::link(&self.shaders, &self.attrib_locations, feedback, false).map_err(|e| vec![e])
+    }
+}
+
+/// An active vertex attribute of a linked Program
+#[derive(Clone, Debug)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub location: GLint,
+    /// e.g: gl::FLOAT_VEC2
+    pub gl_type: GLenum,
+    pub size: GLint,
+}
+
+/// An active uniform of a linked Program
+#[derive(Clone, Debug)]
+pub struct UniformInfo {
+    /// Arrays are named after their first element, e.g: `u_weights[0]`
+    pub name: String,
+    /// e.g: gl::FLOAT_MAT4
+    pub gl_type: GLenum,
+    pub size: GLint,
+    pub location: GLint,
+}
+
+/// (component count, component type) of an attribute type, e.g: gl::FLOAT_VEC3 => (3, gl::FLOAT)
+fn attribute_components(gl_type: GLenum) -> Option<(GLint, GLenum)> {
+    match gl_type {
+        gl::FLOAT => Some((1, gl::FLOAT)),
+        gl::FLOAT_VEC2 => Some((2, gl::FLOAT)),
+        gl::FLOAT_VEC3 => Some((3, gl::FLOAT)),
+        gl::FLOAT_VEC4 => Some((4, gl::FLOAT)),
+        gl::INT => Some((1, gl::INT)),
+        gl::INT_VEC2 => Some((2, gl::INT)),
+        gl::INT_VEC3 => Some((3, gl::INT)),
+        gl::INT_VEC4 => Some((4, gl::INT)),
+        gl::UNSIGNED_INT => Some((1, gl::UNSIGNED_INT)),
+        gl::UNSIGNED_INT_VEC2 => Some((2, gl::UNSIGNED_INT)),
+        gl::UNSIGNED_INT_VEC3 => Some((3, gl::UNSIGNED_INT)),
+        gl::UNSIGNED_INT_VEC4 => Some((4, gl::UNSIGNED_INT)),
+        _ => None,
+    }
+}
+
+/// Checks that every attribute `program` reads is enabled in `vao` with a matching type and
+/// no more components than it declares, mismatches otherwise render silently black. Fewer
+/// components are fine, e.g: vec3 data feeding a vec4 input gets w = 1
+pub fn validate_pipeline(vao: &Vao, program: &Program) -> Result<(), GlError> {
+    let mut errors = Vec::new();
+
+    let mut previous_vao: GLint = 0;
+    unsafe {
+        gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut previous_vao);
+    }
+    vao.bind();
+
+    for attribute in program.active_attributes() {
+        // Built-ins like gl_VertexID have no location
+        if attribute.location < 0 {
+            continue;
+        }
+        let location = attribute.location as GLuint;
+
+        let mut enabled: GLint = 0;
+        let mut components: GLint = 0;
+        let mut component_type: GLint = 0;
+        let mut integer: GLint = 0;
+        unsafe {
+            gl::GetVertexAttribiv(location, gl::VERTEX_ATTRIB_ARRAY_ENABLED, &mut enabled);
+            gl::GetVertexAttribiv(location, gl::VERTEX_ATTRIB_ARRAY_SIZE, &mut components);
+            gl::GetVertexAttribiv(location, gl::VERTEX_ATTRIB_ARRAY_TYPE, &mut component_type);
+            gl::GetVertexAttribiv(location, gl::VERTEX_ATTRIB_ARRAY_INTEGER, &mut integer);
+        }
+
+        if enabled == 0 {
+            errors.push(format!(
+                "Attribute {} (location {}) is not enabled in Vao {}",
+                attribute.name,
+                location,
+                vao.id()
+            ));
+            continue;
+        }
+
+        let Some((expected_components, expected_type)) = attribute_components(attribute.gl_type)
+        else {
+            continue;
+        };
+
+        if components > expected_components {
+            errors.push(format!(
+                "Attribute {} (location {}) expects at most {} components but Vao {} provides {}",
+                attribute.name,
+                location,
+                expected_components,
+                vao.id(),
+                components
+            ));
+        }
+
+        // Integer attributes need gl::VertexAttribIPointer(..), float ones must not use it
+        let shader_is_integer = expected_type != gl::FLOAT;
+        if shader_is_integer != (integer != 0) {
+            errors.push(format!(
+                "Attribute {} (location {}) is {} in the shader but Vao {} feeds {} data (type 0x{:X})",
+                attribute.name,
+                location,
+                if shader_is_integer { "integer" } else { "float" },
+                vao.id(),
+                if integer != 0 { "integer" } else { "float" },
+                component_type
+            ));
+        }
+    }
+
+    GlStateCache::bind_vertex_array(previous_vao as GLuint);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(GlError::PipelineMismatch(errors))
+    }
+}
+
 fn create_whitespace_cstring_with_len(len: usize) -> CString {
     let mut buffer: Vec<u8> = vec![b' '; len];
     buffer.push(0);
     unsafe { CString::from_vec_unchecked(buffer) }
 }
 
-pub fn create_program() -> Result<Program, Box<dyn Error>> {
-    let vert_src = std::fs::read("./src/vert.glsl")?;
-    let frag_src = std::fs::read("./src/frag.glsl")?;
+/// Replaces every `#include "file.glsl"` line with the contents of that file (relative to `dir`
+/// for `source`, to the including file for nested includes), GLSL has no include mechanism of
+/// its own. A file that ends up including itself is an error
+pub fn resolve_includes(source: &str, dir: &Path) -> Result<String, GlError> {
+    resolve_includes_with_files(source, dir).map(|(resolved, _)| resolved)
+}
+
+/// `resolve_includes` that also returns every file that was included, e.g: to check whether a
+/// cached program is older than any of them
+pub fn resolve_includes_with_files(
+    source: &str,
+    dir: &Path,
+) -> Result<(String, Vec<PathBuf>), GlError> {
+    let mut files = Vec::new();
+    let resolved = resolve_includes_nested(source, dir, &mut Vec::new(), &mut files)?;
+    Ok((resolved, files))
+}
+
+/// `including` is the chain of files currently being resolved, a file showing up in it again
+/// would recurse forever
+fn resolve_includes_nested(
+    source: &str,
+    dir: &Path,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<String, GlError> {
+    let mut resolved = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        let included = line
+            .trim()
+            .strip_prefix("#include")
+            .map(|rest| rest.trim().trim_matches('"'));
+
+        match included {
+            Some(file) => {
+                let path = dir.join(file);
+                let included_src = read_source(&path)?;
+                let path = std::fs::canonicalize(&path).unwrap_or(path);
+                if including.contains(&path) {
+                    including.push(path);
+                    return Err(GlError::IncludeCycle(std::mem::take(including)));
+                }
+
+                let nested_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+                including.push(path.clone());
+                files.push(path);
+                resolved.push_str(&resolve_includes_nested(
+                    &included_src,
+                    &nested_dir,
+                    including,
+                    files,
+                )?);
+                including.pop();
+            }
+            None => resolved.push_str(line),
+        }
+        resolved.push('\n');
+    }
+
+    Ok(resolved)
+}
+
+/// A `uniform` declaration found in GLSL source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlslUniformDecl {
+    pub name: String,
+    pub type_str: String,
+    /// `None` for plain uniforms and for array sizes that aren't an integer literal
+    pub array_size: Option<u32>,
+}
+
+/// Lists the `uniform TYPE NAME;` and `uniform TYPE NAME[N];` declarations of a shader source
+/// without compiling it, commented out ones are skipped. A leading `layout(..)` and
+/// initializers are ignored, uniform blocks and declarations spanning several lines are not
+/// reported
+pub fn parse_glsl_uniforms(source: &str) -> Vec<GlslUniformDecl> {
+    let mut uniforms = Vec::new();
+
+    for line in strip_glsl_comments(source).lines() {
+        for statement in line.split(';') {
+            // `uniform Block {`, the members aren't uniforms of their own
+            if statement.contains('{') {
+                continue;
+            }
+            let statement = statement.trim_start();
+            let statement = statement
+                .strip_prefix("layout")
+                .and_then(|rest| rest.trim_start().strip_prefix('('))
+                .and_then(|rest| rest.split_once(')'))
+                .map_or(statement, |(_, rest)| rest);
+
+            let mut tokens = statement.split_whitespace();
+            if tokens.next() != Some("uniform") {
+                continue;
+            }
+
+            let mut tokens = tokens.skip_while(|t| matches!(*t, "lowp" | "mediump" | "highp"));
+            let Some(type_str) = tokens.next() else {
+                continue;
+            };
+
+            let declarators = tokens.collect::<Vec<_>>().join("");
+            for declarator in split_declarators(&declarators) {
+                let declarator = declarator.split('=').next().unwrap_or_default();
+                if declarator.is_empty() {
+                    continue;
+                }
+                let (name, array_size) = match declarator.split_once('[') {
+                    Some((name, size)) => (name, size.trim_end_matches(']').parse().ok()),
+                    None => (declarator, None),
+                };
+
+                uniforms.push(GlslUniformDecl {
+                    name: name.to_string(),
+                    type_str: type_str.to_string(),
+                    array_size,
+                });
+            }
+        }
+    }
+
+    uniforms
+}
+
+/// Splits `a = vec2(0, 1), b[2]` at the commas that aren't inside an initializer
+fn split_declarators(declarators: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in declarators.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(&declarators[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&declarators[start..]);
+    split
+}
+
+/// Replaces `//` and `/* .. */` comments with a space, block comments keep their line breaks
+/// so the lines around them stay apart
+fn strip_glsl_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    loop {
+        let (start, block) = match (rest.find("//"), rest.find("/*")) {
+            (Some(line), Some(block)) if block < line => (block, true),
+            (Some(line), _) => (line, false),
+            (None, Some(block)) => (block, true),
+            (None, None) => break,
+        };
+        stripped.push_str(&rest[..start]);
+        stripped.push(' ');
+
+        let comment = &rest[start + 2..];
+        let end = if block {
+            let end = comment.find("*/").map_or(comment.len(), |end| end + 2);
+            stripped.extend(comment[..end].chars().filter(|&c| c == '\n'));
+            end
+        } else {
+            comment.find('\n').unwrap_or(comment.len())
+        };
+        rest = &comment[end..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// The blackhole shaders, absolute so the binary can be started from any directory
+pub const VERT_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/vert.glsl");
+pub const FRAG_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/frag.glsl");
+
+/// Debug builds read the blackhole shaders from disk so edits show up on reload (R),
+/// release builds use the sources embedded at compile time and run without the src directory
+pub fn create_program() -> Result<Program, GlError> {
+    if cfg!(debug_assertions) {
+        create_program_from_files(
+            Path::new(VERT_SHADER_PATH),
+            Path::new(FRAG_SHADER_PATH),
+            None,
+        )
+    } else {
+        create_program_from_str(include_str!("vert.glsl"), include_str!("frag.glsl"))
+    }
+}
+
+/// `geom` adds a geometry shader between the vertex and fragment stage
+pub fn create_program_from_files(
+    vert: &Path,
+    frag: &Path,
+    geom: Option<&Path>,
+) -> Result<Program, GlError> {
+    create_program_with_progress(vert, frag, geom, |_| {})
+}
+
+/// Like `create_program_from_files` but loads the program from the binary at `cache` if it
+/// is newer than both sources and every file they `#include`. Otherwise, or if the driver
+/// rejects the binary, the sources are compiled and the cache is rewritten. Failing to write
+/// it is only printed
+pub fn create_program_cached(vert: &Path, frag: &Path, cache: &Path) -> Result<Program, GlError> {
+    let resolve = |path: &Path| -> Result<(String, Vec<PathBuf>), GlError> {
+        let source = read_source(path)?;
+        resolve_includes_with_files(&source, path.parent().unwrap_or(Path::new(".")))
+    };
+    let (vert_source, vert_includes) = resolve(vert)?;
+    let (frag_source, frag_includes) = resolve(frag)?;
+
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let cache_is_fresh = modified(cache).is_some_and(|cache| {
+        [vert, frag]
+            .into_iter()
+            .chain(vert_includes.iter().map(PathBuf::as_path))
+            .chain(frag_includes.iter().map(PathBuf::as_path))
+            .all(|source| modified(source).is_some_and(|source| cache >= source))
+    });
+
+    if cache_is_fresh {
+        match Program::from_binary(cache) {
+            Ok(program) => return Ok(program),
+            Err(e) => eprintln!("Ignoring the program cache {}: {}", cache.display(), e),
+        }
+    }
+
+    let shaders = [
+        Shader::from_source_str(&vert_source, gl::VERTEX_SHADER)?,
+        Shader::from_source_str(&frag_source, gl::FRAGMENT_SHADER)?,
+    ];
+    let program = Program::link(&shaders, &[], None, true)?;
+    if let Err(e) = program.binary_save(cache) {
+        eprintln!(
+            "Couldn't write the program cache {}: {}",
+            cache.display(),
+            e
+        );
+    }
+    Ok(program)
+}
+
+/// Links a vertex/fragment program from in-memory sources, no files involved
+pub fn create_program_from_str(vert: &str, frag: &str) -> Result<Program, GlError> {
+    let vert_shader = Shader::from_source_str(vert, gl::VERTEX_SHADER)?;
+    let frag_shader = Shader::from_source_str(frag, gl::FRAGMENT_SHADER)?;
+    Program::from_shaders(&[vert_shader, frag_shader])
+}
+
+/// Reads a shader file, errors name the absolute path that was tried
+fn read_source(path: &Path) -> Result<String, GlError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        GlError::IoError(io::Error::new(
+            e.kind(),
+            format!("{}: {}", absolute.display(), e),
+        ))
+    })
+}
+
+/// Steps of `create_program_with_progress`, reported before each one starts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompileStage {
+    ReadingVert,
+    CompilingVert,
+    /// Only reported when a geometry shader is given
+    ReadingGeom,
+    CompilingGeom,
+    ReadingFrag,
+    CompilingFrag,
+    Linking,
+    Done,
+}
 
-    let vert_c = CString::new(vert_src)?;
-    let frag_c = CString::new(frag_src)?;
+/// Like `create_program` but for any shader files, `callback` is called before every stage
+/// so slow compiles can show progress. `#include`s are resolved relative to each file
+pub fn create_program_with_progress(
+    vert: &Path,
+    frag: &Path,
+    geom: Option<&Path>,
+    callback: impl Fn(CompileStage),
+) -> Result<Program, GlError> {
+    let read = |path: &Path| -> Result<CString, GlError> {
+        let source = read_source(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Ok(CString::new(resolve_includes(&source, dir)?)?)
+    };
+
+    callback(CompileStage::ReadingVert);
+    let vert_c = read(vert)?;
+    callback(CompileStage::CompilingVert);
+    let mut shaders = vec![Shader::from_source(&vert_c, gl::VERTEX_SHADER)?];
+
+    if let Some(geom) = geom {
+        callback(CompileStage::ReadingGeom);
+        let geom_c = read(geom)?;
+        callback(CompileStage::CompilingGeom);
+        shaders.push(Shader::from_source(&geom_c, gl::GEOMETRY_SHADER)?);
+    }
 
-    let vert_shader = Shader::from_source(&vert_c, gl::VERTEX_SHADER)?;
-    let frag_shader = Shader::from_source(&frag_c, gl::FRAGMENT_SHADER)?;
+    callback(CompileStage::ReadingFrag);
+    let frag_c = read(frag)?;
+    callback(CompileStage::CompilingFrag);
+    shaders.push(Shader::from_source(&frag_c, gl::FRAGMENT_SHADER)?);
 
-    let shader_program = Program::from_shaders(&[vert_shader, frag_shader])?;
+    callback(CompileStage::Linking);
+    let shader_program = Program::from_shaders(&shaders)?;
+    callback(CompileStage::Done);
 
     Ok(shader_program)
 }
 
+/// A vertex type that knows how its attributes are laid out in memory, implement it for
+/// custom vertex structs to use them with `Vbo<V>` and `Vao::set_layout::<V>()`
+pub trait VertexLayout: Copy {
+    /// This sets up the vertex attributes in memory that get sent to the shader
+    fn describe();
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
-    pub position: (f32, f32),
-    pub color: (f32, f32, f32),
-    pub tex_coord: (f32, f32),
+    pub position: Vec2,
+    pub color: Vec3,
+    pub tex_coord: Vec2,
 }
 impl Vertex {
-    pub fn new(pos: (f32, f32), color: (f32, f32, f32), tex_coord: (f32, f32)) -> Self {
+    pub fn new(pos: impl Into<Vec2>, color: impl Into<Vec3>, tex_coord: impl Into<Vec2>) -> Self {
         Self {
-            position: pos,
-            color,
-            tex_coord,
+            position: pos.into(),
+            color: color.into(),
+            tex_coord: tex_coord.into(),
         }
     }
 
-    /// This sets up the vertex attributes in memory that get sent to the shader
-    pub fn desc() {
+    /// For untextured meshes, tex_coord is (0.0, 0.0)
+    pub fn new_pos_color(pos: impl Into<Vec2>, color: impl Into<Vec3>) -> Self {
+        Self::new(pos, color, (0.0, 0.0))
+    }
+
+    /// e.g: `Vertex::new_pos_color(pos, color).with_tex_coord((1.0, 0.0))`
+    pub fn with_tex_coord(mut self, tex_coord: impl Into<Vec2>) -> Self {
+        self.tex_coord = tex_coord.into();
+        self
+    }
+}
+
+impl VertexLayout for Vertex {
+    fn describe() {
         let stride = size_of::<Self>();
 
         unsafe {
@@ -194,51 +1207,336 @@ impl Vertex {
     }
 }
 
-/// Vertex Buffer Object
-pub struct Vbo {
-    pub id: GLuint,
+/// Vertex for 3D geometry (jets, disk mesh), `Vertex` stays the 2D screen-space one
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex3D {
+    pub position: (f32, f32, f32),
+    pub color: (f32, f32, f32),
+    pub tex_coord: (f32, f32),
+    pub normal: (f32, f32, f32),
 }
-
-impl Vbo {
-    pub fn generate() -> Self {
-        let mut id: GLuint = 0;
-        unsafe {
-            gl::GenBuffers(1, &mut id);
+impl Vertex3D {
+    pub fn new(
+        pos: (f32, f32, f32),
+        color: (f32, f32, f32),
+        tex_coord: (f32, f32),
+        normal: (f32, f32, f32),
+    ) -> Self {
+        Self {
+            position: pos,
+            color,
+            tex_coord,
+            normal,
         }
-        Vbo { id }
+    }
+}
+
+impl VertexLayout for Vertex3D {
+    fn describe() {
+        let stride = size_of::<Self>();
+
+        unsafe {
+            // Vertex Position
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                0,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(Vertex3D, position) as *const GLvoid,
+            );
+
+            // Vertex Color
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(Vertex3D, color) as *const GLvoid,
+            );
+
+            // TexCoord Coords
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(
+                2,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(Vertex3D, tex_coord) as *const GLvoid,
+            );
+
+            // Vertex Normal
+            gl::EnableVertexAttribArray(3);
+            gl::VertexAttribPointer(
+                3,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(Vertex3D, normal) as *const GLvoid,
+            );
+        }
+    }
+}
+
+/// Per-instance data for InstanceVbo, e.g: one star of the background. `offset` is read at
+/// `layout (location=3)` and `scale` at `layout (location=4)`, after the `Vertex` attributes
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceData {
+    pub offset: (f32, f32),
+    pub scale: f32,
+}
+
+impl VertexLayout for InstanceData {
+    fn describe() {
+        let stride = size_of::<Self>();
+
+        unsafe {
+            // Instance Offset
+            gl::EnableVertexAttribArray(3);
+            gl::VertexAttribPointer(
+                3,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(InstanceData, offset) as *const GLvoid,
+            );
+
+            // Instance Scale
+            gl::EnableVertexAttribArray(4);
+            gl::VertexAttribPointer(
+                4,
+                1,
+                gl::FLOAT,
+                gl::FALSE,
+                stride as GLint,
+                offset_of!(InstanceData, scale) as *const GLvoid,
+            );
+        }
+    }
+}
+
+/// Usage hint of gl::BufferData(..), where the driver places the buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times
+    StaticDraw,
+    /// Updated repeatedly and drawn many times
+    #[default]
+    DynamicDraw,
+    /// Updated about every time it's drawn
+    StreamDraw,
+}
+
+impl BufferUsage {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            BufferUsage::StaticDraw => gl::STATIC_DRAW,
+            BufferUsage::DynamicDraw => gl::DYNAMIC_DRAW,
+            BufferUsage::StreamDraw => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// `count` buffer names from one gl::GenBuffers(..) call
+fn gen_buffers(count: usize) -> Vec<GLuint> {
+    let mut ids: Vec<GLuint> = vec![0; count];
+    unsafe {
+        gl::GenBuffers(count as GLsizei, ids.as_mut_ptr());
+    }
+    ids
+}
+
+/// Primitive type of the draw calls, how the vertices are assembled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Primitive {
+    #[default]
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Points,
+    /// Input of a tessellation control shader, see gl::PatchParameteri(..)
+    Patches,
+}
+
+impl Primitive {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            Primitive::Triangles => gl::TRIANGLES,
+            Primitive::TriangleStrip => gl::TRIANGLE_STRIP,
+            Primitive::TriangleFan => gl::TRIANGLE_FAN,
+            Primitive::Lines => gl::LINES,
+            Primitive::LineStrip => gl::LINE_STRIP,
+            Primitive::LineLoop => gl::LINE_LOOP,
+            Primitive::Points => gl::POINTS,
+            Primitive::Patches => gl::PATCHES,
+        }
+    }
+}
+
+/// Vertex Buffer Object
+pub struct Vbo<V: VertexLayout> {
+    pub id: GLuint,
+    usage: BufferUsage,
+    /// Bytes allocated by the last `set`
+    size: usize,
+    /// Immutable storage from `generate_persistent`, `set` can't reallocate it
+    persistent: bool,
+    _vertex: PhantomData<V>,
+}
+
+impl<V: VertexLayout> Vbo<V> {
+    /// Shorthand for `generate_with_usage(BufferUsage::DynamicDraw)`
+    pub fn generate() -> Self {
+        Self::generate_with_usage(BufferUsage::DynamicDraw)
+    }
+
+    /// `usage` is used for every upload, e.g: BufferUsage::StaticDraw for geometry that never changes
+    pub fn generate_with_usage(usage: BufferUsage) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        Vbo {
+            id,
+            usage,
+            size: 0,
+            persistent: false,
+            _vertex: PhantomData,
+        }
+    }
+
+    /// `count` Vbos from a single gl::GenBuffers(..) call, e.g: when setting up many meshes
+    pub fn generate_n(count: usize) -> Vec<Self> {
+        gen_buffers(count)
+            .into_iter()
+            .map(|id| Vbo {
+                id,
+                usage: BufferUsage::DynamicDraw,
+                size: 0,
+                persistent: false,
+                _vertex: PhantomData,
+            })
+            .collect()
+    }
+
+    /// Allocates room for `capacity` vertices with gl::BufferStorage(..) so the buffer can stay
+    /// mapped while it's drawn from, see `map_write`. The size is fixed, `set` can't be used
+    /// but `update_sub_data` can. Needs an OpenGL 4.4 context
+    pub fn generate_persistent(capacity: usize) -> Self {
+        let mut vbo = Self::generate_with_usage(BufferUsage::StreamDraw);
+        vbo.size = capacity * size_of::<V>();
+        vbo.persistent = true;
+        vbo.bind();
+        unsafe {
+            gl::BufferStorage(
+                gl::ARRAY_BUFFER,
+                vbo.size as GLsizeiptr,
+                null(),
+                gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT,
+            );
+        }
+        vbo
+    }
+
+    /// Maps the whole buffer for writing. Writes to a buffer from `generate_persistent` are
+    /// visible to draws while the map is alive, any other buffer must be unmapped (dropped)
+    /// before drawing from it. The old contents of a non persistent buffer are undefined
+    pub fn map_write(&self) -> Result<BufferMap<'_, V>, GlError> {
+        let flags = if self.persistent {
+            gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT
+        } else {
+            gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_BUFFER_BIT
+        };
+
+        self.bind();
+        let ptr =
+            unsafe { gl::MapBufferRange(gl::ARRAY_BUFFER, 0, self.size as GLsizeiptr, flags) };
+        if ptr.is_null() {
+            return Err(GlError::BufferMapFailed { id: self.id });
+        }
+
+        Ok(BufferMap {
+            id: self.id,
+            ptr: ptr as *mut V,
+            len: self.size / size_of::<V>(),
+            _buffer: PhantomData,
+        })
     }
 
-    pub fn set(&self, data: &[Vertex]) {
+    pub fn set(&mut self, data: &[V]) {
+        debug_assert!(
+            !self.persistent,
+            "Vbo {} has fixed size storage, use update_sub_data",
+            self.id
+        );
         self.bind();
         self.data(data);
+        self.size = size_of_val(data);
     }
 
-    fn data(&self, vertices: &[Vertex]) {
+    fn data(&self, vertices: &[V]) {
         unsafe {
             gl::BufferData(
                 gl::ARRAY_BUFFER,
                 std::mem::size_of_val(vertices) as GLsizeiptr,
                 vertices.as_ptr() as *const GLvoid,
-                gl::DYNAMIC_DRAW,
+                self.usage.to_gl(),
             );
         }
     }
 
-    fn bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
-        }
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Bytes allocated by the last `set`
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    fn unbind(&self) {
+    /// Overwrites the vertices starting at index `offset` without reallocating the buffer,
+    /// they have to fit into what the last `set` uploaded
+    pub fn update_sub_data(&self, offset: usize, vertices: &[V]) {
+        debug_assert!(
+            (offset + vertices.len()) * size_of::<V>() <= self.size,
+            "update_sub_data writes past the {} bytes of Vbo {}",
+            self.size,
+            self.id
+        );
+        self.bind();
         unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                (offset * size_of::<V>()) as GLintptr,
+                std::mem::size_of_val(vertices) as GLsizeiptr,
+                vertices.as_ptr() as *const GLvoid,
+            );
         }
     }
 
+    pub fn bind(&self) {
+        GlStateCache::bind_buffer(gl::ARRAY_BUFFER, self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_buffer(gl::ARRAY_BUFFER, 0);
+    }
+
     fn delete(&self) {
         unsafe {
             gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
         }
     }
 
@@ -247,58 +1545,315 @@ impl Vbo {
     }
 }
 
-impl Drop for Vbo {
+impl<V: VertexLayout> Drop for Vbo<V> {
+    fn drop(&mut self) {
+        self.unbind();
+        self.delete();
+    }
+}
+
+/// Mapped Vbo memory from `Vbo::map_write`, unmapped again when dropped
+pub struct BufferMap<'a, T> {
+    id: GLuint,
+    ptr: *mut T,
+    len: usize,
+    _buffer: PhantomData<&'a mut [T]>,
+}
+
+impl<T> Deref for BufferMap<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for BufferMap<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for BufferMap<'_, T> {
     fn drop(&mut self) {
+        unsafe {
+            GlStateCache::bind_buffer(gl::ARRAY_BUFFER, self.id);
+            gl::UnmapBuffer(gl::ARRAY_BUFFER);
+        }
+    }
+}
+
+/// Vbo of per-instance data, the attributes `I::describe()` sets up advance once per instance
+/// instead of once per vertex
+pub struct InstanceVbo<I: VertexLayout> {
+    pub vbo: Vbo<I>,
+    count: usize,
+}
+
+impl<I: VertexLayout> InstanceVbo<I> {
+    pub fn generate() -> Self {
+        InstanceVbo {
+            vbo: Vbo::generate(),
+            count: 0,
+        }
+    }
+
+    pub fn set(&mut self, instances: &[I]) {
+        self.vbo.set(instances);
+        self.count = instances.len();
+    }
+
+    /// Instances uploaded by the last `set`
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Adds the per-instance attributes to `vao` next to its per-vertex ones, has to be
+    /// called again after `vao` is re-set up
+    pub fn attach(&self, vao: &Vao) {
+        vao.bind();
+        self.vbo.bind();
+        I::describe();
+
+        // Only the attributes sourced from this buffer get the divisor, describe() doesn't
+        // report which locations it used
+        let mut max_attribs: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_VERTEX_ATTRIBS, &mut max_attribs);
+            for attrib in 0..max_attribs as GLuint {
+                let mut buffer: GLint = 0;
+                gl::GetVertexAttribiv(attrib, gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING, &mut buffer);
+                if buffer as GLuint == self.vbo.id {
+                    gl::VertexAttribDivisor(attrib, 1);
+                }
+            }
+        }
+        vao.unbind();
+    }
+}
+
+/// Transform feedback object, records the outputs selected with
+/// `ProgramBuilder::set_transform_feedback_varyings` into Vbos, e.g: particles updated on
+/// the GPU. Usually drawn with gl::RASTERIZER_DISCARD enabled
+pub struct TransformFeedback {
+    pub id: GLuint,
+}
+
+impl TransformFeedback {
+    pub fn generate() -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTransformFeedbacks(1, &mut id);
+        }
+        TransformFeedback { id }
+    }
+
+    /// Output `binding` is written into `vbo` from its start, `vbo` has to be big enough
+    pub fn bind_output_buffer<V: VertexLayout>(&self, vbo: &Vbo<V>, binding: u32) {
+        self.bind();
+        GlStateCache::bind_buffer_base(gl::TRANSFORM_FEEDBACK_BUFFER, binding, vbo.id());
+    }
+
+    /// Starts recording, `primitive` is Points, Lines or Triangles and has to match what's
+    /// drawn until `end`
+    pub fn begin(&self, primitive: Primitive) {
+        debug_assert!(
+            matches!(
+                primitive,
+                Primitive::Points | Primitive::Lines | Primitive::Triangles
+            ),
+            "TransformFeedback::begin takes Points, Lines or Triangles, not {:?}",
+            primitive
+        );
+        self.bind();
+        unsafe {
+            gl::BeginTransformFeedback(primitive.to_gl());
+        }
+    }
+
+    pub fn end(&self) {
+        unsafe {
+            gl::EndTransformFeedback();
+        }
         self.unbind();
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindTransformFeedback(gl::TRANSFORM_FEEDBACK, self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindTransformFeedback(gl::TRANSFORM_FEEDBACK, 0);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTransformFeedbacks(1, &self.id);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for TransformFeedback {
+    fn drop(&mut self) {
         self.delete();
     }
 }
 
+/// Integer types that can be stored in an Ibo
+pub trait IndexElement: Copy {
+    /// The type passed to gl::DrawElements(..)
+    const GL_TYPE: GLenum;
+}
+
+impl IndexElement for u8 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl IndexElement for u16 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl IndexElement for u32 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_INT;
+}
+
 /// Index Buffer Object
 pub struct Ibo {
     pub id: GLuint,
+    index_type: GLenum,
+    usage: BufferUsage,
+    /// Bytes allocated by the last `set`
+    size: usize,
 }
 
 impl Ibo {
+    /// Shorthand for `generate_with_usage(BufferUsage::DynamicDraw)`
     pub fn generate() -> Self {
+        Self::generate_with_usage(BufferUsage::DynamicDraw)
+    }
+
+    /// `usage` is used for every upload, e.g: BufferUsage::StaticDraw for geometry that never changes
+    pub fn generate_with_usage(usage: BufferUsage) -> Self {
         let mut id: GLuint = 0;
         unsafe {
             gl::GenBuffers(1, &mut id);
         }
-        Ibo { id }
+        Ibo {
+            id,
+            index_type: gl::UNSIGNED_INT,
+            usage,
+            size: 0,
+        }
+    }
+
+    /// `count` Ibos from a single gl::GenBuffers(..) call, see `Vbo::generate_n`
+    pub fn generate_n(count: usize) -> Vec<Self> {
+        gen_buffers(count)
+            .into_iter()
+            .map(|id| Ibo {
+                id,
+                index_type: gl::UNSIGNED_INT,
+                usage: BufferUsage::DynamicDraw,
+                size: 0,
+            })
+            .collect()
     }
 
-    pub fn set(&self, data: &[u32]) {
+    pub fn set<T: IndexElement>(&mut self, data: &[T]) {
         self.bind();
         self.data(data);
+        self.index_type = T::GL_TYPE;
+        self.size = size_of_val(data);
     }
 
-    fn data(&self, indices: &[u32]) {
+    fn data<T: IndexElement>(&self, indices: &[T]) {
         unsafe {
             gl::BufferData(
                 gl::ELEMENT_ARRAY_BUFFER,
                 std::mem::size_of_val(indices) as GLsizeiptr,
                 indices.as_ptr() as *const GLvoid,
-                gl::DYNAMIC_DRAW,
+                self.usage.to_gl(),
+            );
+        }
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Bytes allocated by the last `set`
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// gl::UNSIGNED_INT, gl::UNSIGNED_SHORT or gl::UNSIGNED_BYTE, whatever the last `set` uploaded
+    pub fn index_type(&self) -> GLenum {
+        self.index_type
+    }
+
+    /// Overwrites the indices starting at index `offset` without reallocating the buffer,
+    /// they have to fit into what the last `set` uploaded and `T` has to match its type.
+    /// Bind the owning Vao first
+    pub fn update_sub_data<T: IndexElement>(&self, offset: usize, indices: &[T]) {
+        debug_assert!(
+            (offset + indices.len()) * size_of::<T>() <= self.size,
+            "update_sub_data writes past the {} bytes of Ibo {}",
+            self.size,
+            self.id
+        );
+        debug_assert_eq!(T::GL_TYPE, self.index_type);
+        self.bind();
+        unsafe {
+            gl::BufferSubData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (offset * size_of::<T>()) as GLintptr,
+                std::mem::size_of_val(indices) as GLsizeiptr,
+                indices.as_ptr() as *const GLvoid,
             );
         }
     }
 
-    fn bind(&self) {
+    /// Draws `count` indices with the index type of the last upload, the matching Vao must be bound
+    pub fn draw_elements(&self, mode: Primitive, count: i32) {
+        unsafe {
+            gl::DrawElements(mode.to_gl(), count, self.index_type, null());
+        }
+    }
+
+    /// Like `draw_elements` but draws `instances` copies, see InstanceVbo
+    pub fn draw_elements_instanced(&self, mode: Primitive, count: i32, instances: i32) {
         unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
+            gl::DrawElementsInstanced(mode.to_gl(), count, self.index_type, null(), instances);
         }
     }
 
-    fn unbind(&self) {
+    /// Issues the first `count` commands of the bound IndirectBuffer in one call
+    pub fn multi_draw_elements_indirect(&self, mode: Primitive, count: i32) {
         unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            gl::MultiDrawElementsIndirect(mode.to_gl(), self.index_type, null(), count, 0);
         }
     }
 
+    pub fn bind(&self) {
+        GlStateCache::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+    }
+
     fn delete(&self) {
         unsafe {
             gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
         }
     }
 
@@ -328,26 +1883,64 @@ impl Vao {
         Vao { id }
     }
 
+    /// `count` Vaos from a single gl::GenVertexArrays(..) call, see `Vbo::generate_n`
+    pub fn generate_n(count: usize) -> Vec<Self> {
+        let mut ids: Vec<GLuint> = vec![0; count];
+        unsafe {
+            gl::GenVertexArrays(count as GLsizei, ids.as_mut_ptr());
+        }
+        ids.into_iter().map(|id| Vao { id }).collect()
+    }
+
     pub fn set(&self) {
+        self.set_layout::<Vertex>();
+    }
+
+    /// Like `set` but for any vertex type e.g: `vao.set_layout::<Vertex3D>()`
+    pub fn set_layout<V: VertexLayout>(&self) {
         self.bind();
-        Vertex::desc();
+        V::describe();
     }
 
-    fn bind(&self) {
+    /// Sources float attribute `attrib` from `vbo`, for layouts split over several VBOs
+    /// e.g: per-instance data. `offset` and `stride` are in bytes, the VAO is bound here
+    pub fn bind_vbo_at_attribute<V: VertexLayout>(
+        &self,
+        attrib: u32,
+        vbo: &Vbo<V>,
+        components: i32,
+        gl_type: GLenum,
+        stride: i32,
+        offset: usize,
+    ) {
+        self.bind();
+        vbo.bind();
         unsafe {
-            gl::BindVertexArray(self.id);
+            gl::EnableVertexAttribArray(attrib);
+            gl::VertexAttribPointer(
+                attrib,
+                components,
+                gl_type,
+                gl::FALSE,
+                stride,
+                offset as *const GLvoid,
+            );
         }
     }
 
-    fn unbind(&self) {
-        unsafe {
-            gl::BindVertexArray(0);
-        }
+    /// Switches to this Vao's attribute setup without re-running `describe()`
+    pub fn bind(&self) {
+        GlStateCache::bind_vertex_array(self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_vertex_array(0);
     }
 
     fn delete(&self) {
         unsafe {
             gl::DeleteVertexArrays(1, &self.id);
+            GlStateCache::vertex_array_deleted(self.id);
         }
     }
 
@@ -363,30 +1956,1931 @@ impl Drop for Vao {
     }
 }
 
-/// Uniform Object
+/// Storage format of a texture (the `internalformat` of gl::TexImage2D(..))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InternalFormat {
+    Rgba8,
+    Rgba16F,
+    Rgba32F,
+    Rg16F,
+    R32F,
+    Depth24,
+}
+
+impl InternalFormat {
+    /// (internal format, pixel format, pixel type)
+    pub fn to_gl(self) -> (GLenum, GLenum, GLenum) {
+        match self {
+            InternalFormat::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            InternalFormat::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            InternalFormat::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+            InternalFormat::Rg16F => (gl::RG16F, gl::RG, gl::FLOAT),
+            InternalFormat::R32F => (gl::R32F, gl::RED, gl::FLOAT),
+            InternalFormat::Depth24 => (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::FLOAT),
+        }
+    }
+}
+
+/// Texel filtering of Texture2D::set_filter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+/// How mip levels are picked when a texture is minified, anything but None needs mipmaps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipmapFilter {
+    /// Only level 0 is sampled
+    None,
+    /// The closest level
+    Nearest,
+    /// Blends the two closest levels (trilinear with TextureFilter::Linear)
+    Linear,
+}
+
+/// 2D Texture
+pub struct Texture2D {
+    pub id: GLuint,
+    width: u32,
+    height: u32,
+    format: InternalFormat,
+    /// Set by `generate_mipmaps`, reallocating drops the mip chain
+    mipmapped: bool,
+}
+
+impl Texture2D {
+    pub fn generate() -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+        }
+        Texture2D {
+            id,
+            width: 0,
+            height: 0,
+            format: InternalFormat::Rgba8,
+            mipmapped: false,
+        }
+    }
+
+    /// Allocates uninitialized storage, e.g: for use as a Framebuffer attachment.
+    /// Filtering is set to linear and wrapping to clamp-to-edge
+    pub fn allocate(&mut self, width: u32, height: u32, format: InternalFormat) {
+        let (internal, pixel_format, pixel_type) = format.to_gl();
+        self.width = width;
+        self.height = height;
+        self.format = format;
+        self.mipmapped = false;
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal as GLint,
+                width as GLint,
+                height as GLint,
+                0,
+                pixel_format,
+                pixel_type,
+                null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+        }
+    }
+
+    /// Uploads tightly packed RGBA8 pixels (bottom row first), e.g: a star field background.
+    /// Filtering and wrapping are set like in `allocate`
+    pub fn upload_rgba(&mut self, width: u32, height: u32, data: &[u8]) {
+        assert_eq!(
+            data.len(),
+            width as usize * height as usize * 4,
+            "RGBA8 data doesn't match a {}x{} texture",
+            width,
+            height
+        );
+        self.allocate(width, height, InternalFormat::Rgba8);
+
+        unsafe {
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                width as GLint,
+                height as GLint,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+
+    /// Loads a PNG, JPEG or BMP as RGBA8 with a full mip chain and trilinear filtering
+    pub fn load_from_file(path: &Path) -> Result<Self, GlError> {
+        let image = image::open(path).map_err(|e| {
+            let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+            let kind = match &e {
+                image::ImageError::IoError(e) => e.kind(),
+                _ => io::ErrorKind::InvalidData,
+            };
+            GlError::IoError(io::Error::new(
+                kind,
+                format!("{}: {}", absolute.display(), e),
+            ))
+        })?;
+        // Images start at the top row, GL at the bottom one
+        let image = image.flipv().into_rgba8();
+        let (width, height) = image.dimensions();
+
+        let mut texture = Self::generate();
+        texture.upload_rgba(width, height, image.as_raw());
+        texture.generate_mipmaps();
+        texture.set_filter(
+            TextureFilter::Linear,
+            TextureFilter::Linear,
+            MipmapFilter::Linear,
+        );
+        Ok(texture)
+    }
+
+    /// Builds the mip chain from level 0 with gl::GenerateMipmap(..), call it again after
+    /// changing level 0. See mipmap::ComputeMipmapGenerator for a defined filter kernel
+    pub fn generate_mipmaps(&mut self) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        self.mipmapped = true;
+    }
+
+    /// For mip chains built outside `generate_mipmaps`, e.g: by ComputeMipmapGenerator
+    pub(crate) fn set_mipmapped(&mut self, mipmapped: bool) {
+        self.mipmapped = mipmapped;
+    }
+
+    /// e.g: `set_filter(TextureFilter::Nearest, TextureFilter::Nearest, MipmapFilter::None)`
+    /// for pixelated lookups. A MipmapFilter other than None needs `generate_mipmaps` first,
+    /// sampling a texture without the mip chain it's filtered with gives black
+    pub fn set_filter(&self, min: TextureFilter, mag: TextureFilter, mipmap: MipmapFilter) {
+        debug_assert!(
+            mipmap == MipmapFilter::None || self.mipmapped,
+            "Texture {} is filtered with mipmaps but has none",
+            self.id
+        );
+        let min = match (min, mipmap) {
+            (TextureFilter::Nearest, MipmapFilter::None) => gl::NEAREST,
+            (TextureFilter::Linear, MipmapFilter::None) => gl::LINEAR,
+            (TextureFilter::Nearest, MipmapFilter::Nearest) => gl::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Linear, MipmapFilter::Nearest) => gl::LINEAR_MIPMAP_NEAREST,
+            (TextureFilter::Nearest, MipmapFilter::Linear) => gl::NEAREST_MIPMAP_LINEAR,
+            (TextureFilter::Linear, MipmapFilter::Linear) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag = match mag {
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR,
+        };
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag as GLint);
+        }
+    }
+
+    /// Sharper minification at glancing angles, e.g: the accretion disk seen edge-on.
+    /// `max_anisotropy` is clamped to 1..driver maximum, without driver support a warning is
+    /// printed and the texture stays as is
+    pub fn set_anisotropic_filtering(&self, max_anisotropy: f32) {
+        if !GpuCaps::current().anisotropic_filtering {
+            eprintln!(
+                "Anisotropic filtering isn't supported, texture {} keeps its filtering",
+                self.id
+            );
+            return;
+        }
+
+        let mut driver_max: f32 = 1.0;
+        unsafe {
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut driver_max);
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameterf(
+                gl::TEXTURE_2D,
+                TEXTURE_MAX_ANISOTROPY,
+                max_anisotropy.clamp(1.0, driver_max.max(1.0)),
+            );
+        }
+    }
+
+    /// e.g: `set_wrap(gl::REPEAT, gl::CLAMP_TO_EDGE)` for a panorama that wraps horizontally
+    pub fn set_wrap(&self, s: GLenum, t: GLenum) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, s as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, t as GLint);
+        }
+    }
+
+    /// Binds the texture to texture unit `slot` e.g: gl::TEXTURE0 + slot
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn format(&self) -> InternalFormat {
+        self.format
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// Cube-map Texture (six square faces)
+pub struct TextureCubemap {
+    pub id: GLuint,
+    size: u32,
+}
+
+impl TextureCubemap {
+    pub fn generate() -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+        }
+        TextureCubemap { id, size: 0 }
+    }
+
+    /// Allocates six `size`x`size` depth faces, e.g: for omnidirectional shadow maps
+    pub fn allocate_depth(&mut self, size: u32) {
+        self.size = size;
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+            for face in 0..6 {
+                gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    0,
+                    gl::DEPTH_COMPONENT24 as GLint,
+                    size as GLint,
+                    size as GLint,
+                    0,
+                    gl::DEPTH_COMPONENT,
+                    gl::FLOAT,
+                    null(),
+                );
+            }
+        }
+        Self::set_linear_clamped();
+    }
+
+    /// Uploads six tightly packed RGBA8 faces in +X, -X, +Y, -Y, +Z, -Z order, e.g: a star
+    /// field skybox sampled with `samplerCube`. Faces have to be square
+    pub fn load_faces(&mut self, faces: [&[u8]; 6], width: u32, height: u32) {
+        assert_eq!(width, height, "cube-map faces have to be square");
+        let face_len = width as usize * height as usize * 4;
+        for (face, data) in faces.iter().enumerate() {
+            assert_eq!(
+                data.len(),
+                face_len,
+                "face {} doesn't hold {}x{} RGBA8 texels",
+                face,
+                width,
+                height
+            );
+        }
+        self.size = width;
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+        }
+        with_pixel_store(gl::UNPACK_ALIGNMENT, 1, || {
+            for (face, data) in (0..).zip(faces) {
+                unsafe {
+                    gl::TexImage2D(
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        0,
+                        gl::RGBA8 as GLint,
+                        width as GLint,
+                        height as GLint,
+                        0,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE,
+                        data.as_ptr() as *const GLvoid,
+                    );
+                }
+            }
+        });
+        Self::set_linear_clamped();
+    }
+
+    /// Linear filtering and edge clamping on all three axes for the bound cube-map
+    fn set_linear_clamped() {
+        let target = gl::TEXTURE_CUBE_MAP;
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+        }
+    }
+
+    /// Binds the texture to texture unit `slot` e.g: gl::TEXTURE0 + slot
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for TextureCubemap {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// Stack of same-size RGBA8 layers in one texture object, sampled with `sampler2DArray` and
+/// the layer as third coordinate so several texture variants share a draw call
+pub struct Texture2DArray {
+    pub id: GLuint,
+    width: u32,
+    height: u32,
+    layers: u32,
+}
+
+impl Texture2DArray {
+    /// Allocates `layers` uninitialized layers, filtering is set to linear and wrapping to
+    /// clamp-to-edge
+    pub fn new(width: u32, height: u32, layers: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, id);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::RGBA8 as GLint,
+                width as GLint,
+                height as GLint,
+                layers as GLint,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                null(),
+            );
+            let target = gl::TEXTURE_2D_ARRAY;
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        }
+        Texture2DArray {
+            id,
+            width,
+            height,
+            layers,
+        }
+    }
+
+    /// Overwrites `layer` with tightly packed RGBA8 pixels (bottom row first)
+    pub fn upload_layer(&self, layer: u32, data: &[u8]) {
+        assert!(
+            layer < self.layers,
+            "layer {} of a {} layer texture array",
+            layer,
+            self.layers
+        );
+        assert_eq!(
+            data.len(),
+            self.width as usize * self.height as usize * 4,
+            "RGBA8 data doesn't match a {}x{} layer",
+            self.width,
+            self.height
+        );
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+            gl::TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                0,
+                0,
+                layer as GLint,
+                self.width as GLint,
+                self.height as GLint,
+                1,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+
+    /// Binds the texture to texture unit `slot` e.g: gl::TEXTURE0 + slot
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for Texture2DArray {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// Sampling parameters kept apart from the texture, bound per texture unit they override the
+/// texture's own filter and wrap settings. Lets the same texture be sampled e.g: nearest in
+/// one pass and linear in the next
+pub struct Sampler {
+    pub id: GLuint,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenSamplers(1, &mut id);
+        }
+        Sampler { id }
+    }
+
+    /// e.g: `set_filter(gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR)`
+    pub fn set_filter(&self, min: GLenum, mag: GLenum) {
+        unsafe {
+            gl::SamplerParameteri(self.id, gl::TEXTURE_MIN_FILTER, min as GLint);
+            gl::SamplerParameteri(self.id, gl::TEXTURE_MAG_FILTER, mag as GLint);
+        }
+    }
+
+    pub fn set_wrap_s(&self, wrap: GLenum) {
+        unsafe {
+            gl::SamplerParameteri(self.id, gl::TEXTURE_WRAP_S, wrap as GLint);
+        }
+    }
+
+    pub fn set_wrap_t(&self, wrap: GLenum) {
+        unsafe {
+            gl::SamplerParameteri(self.id, gl::TEXTURE_WRAP_T, wrap as GLint);
+        }
+    }
+
+    pub fn set_wrap_r(&self, wrap: GLenum) {
+        unsafe {
+            gl::SamplerParameteri(self.id, gl::TEXTURE_WRAP_R, wrap as GLint);
+        }
+    }
+
+    /// Offset added to the computed mip level, positive values pick blurrier levels
+    pub fn set_lod_bias(&self, bias: f32) {
+        unsafe {
+            gl::SamplerParameterf(self.id, gl::TEXTURE_LOD_BIAS, bias);
+        }
+    }
+
+    /// Uses this sampler for whatever texture is bound to unit `slot`
+    pub fn bind(&self, slot: u32) {
+        unsafe {
+            gl::BindSampler(slot, self.id);
+        }
+    }
+
+    /// Back to the texture's own parameters on unit `slot`
+    pub fn unbind(&self, slot: u32) {
+        unsafe {
+            gl::BindSampler(slot, 0);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteSamplers(1, &self.id);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// Attachment point of a Framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FboAttachment {
+    Color(u32),
+    Depth,
+    Stencil,
+    DepthStencil,
+}
+
+impl FboAttachment {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            FboAttachment::Color(index) => gl::COLOR_ATTACHMENT0 + index,
+            FboAttachment::Depth => gl::DEPTH_ATTACHMENT,
+            FboAttachment::Stencil => gl::STENCIL_ATTACHMENT,
+            FboAttachment::DepthStencil => gl::DEPTH_STENCIL_ATTACHMENT,
+        }
+    }
+}
+
+/// Render Buffer Object, storage that can be rendered to but not sampled (e.g: depth)
+pub struct Renderbuffer {
+    pub id: GLuint,
+}
+
+impl Renderbuffer {
+    /// Generates and allocates in one go, `format` is e.g: gl::DEPTH24_STENCIL8
+    pub fn new(width: u32, height: u32, format: GLenum) -> Self {
+        let renderbuffer = Self::generate();
+        renderbuffer.allocate(width, height, format);
+        renderbuffer
+    }
+
+    pub fn generate() -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut id);
+        }
+        Renderbuffer { id }
+    }
+
+    /// `internal_format` is e.g: gl::DEPTH_COMPONENT24 or gl::DEPTH24_STENCIL8
+    pub fn allocate(&self, width: u32, height: u32, internal_format: GLenum) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.id);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                internal_format,
+                width as GLsizei,
+                height as GLsizei,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+    }
+
+    /// Like `allocate` with `samples` samples per pixel, for multisampled Framebuffers
+    pub fn allocate_multisample(
+        &self,
+        width: u32,
+        height: u32,
+        samples: u32,
+        internal_format: GLenum,
+    ) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.id);
+            gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                samples as GLsizei,
+                internal_format,
+                width as GLsizei,
+                height as GLsizei,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.id);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// Frame Buffer Object
+pub struct Framebuffer {
+    pub id: GLuint,
+    width: u32,
+    height: u32,
+    color: Option<Texture2D>,
+    /// Attached renderbuffers created by `new` / `new_multisampled`, they live as long as the FBO
+    renderbuffers: Vec<Renderbuffer>,
+}
+
+impl Framebuffer {
+    pub fn generate(width: u32, height: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+        }
+        Framebuffer {
+            id,
+            width,
+            height,
+            color: None,
+            renderbuffers: Vec::new(),
+        }
+    }
+
+    /// Render target for post-processing: an RGBA8 color texture at attachment 0 and,
+    /// if `depth` is set, a 24 bit depth + 8 bit stencil renderbuffer
+    pub fn new(width: u32, height: u32, depth: bool) -> Result<Self, GlError> {
+        let mut fbo = Self::with_format(width, height, InternalFormat::Rgba8);
+        if depth {
+            let renderbuffer = Renderbuffer::new(width, height, gl::DEPTH24_STENCIL8);
+            fbo.attach_renderbuffer(&renderbuffer, FboAttachment::DepthStencil);
+            fbo.renderbuffers.push(renderbuffer);
+        }
+        fbo.check_status()?;
+        Ok(fbo)
+    }
+
+    /// Render target with `samples` samples per pixel: RGBA8 color at attachment 0 and
+    /// 24 bit depth + 8 bit stencil, both renderbuffers. It can't be sampled, resolve it
+    /// into a regular Framebuffer with `blit_to` first
+    pub fn new_multisampled(width: u32, height: u32, samples: u32) -> Result<Self, GlError> {
+        let mut fbo = Self::generate(width, height);
+
+        let color = Renderbuffer::generate();
+        color.allocate_multisample(width, height, samples, gl::RGBA8);
+        fbo.attach_renderbuffer(&color, FboAttachment::Color(0));
+
+        let depth = Renderbuffer::generate();
+        depth.allocate_multisample(width, height, samples, gl::DEPTH24_STENCIL8);
+        fbo.attach_renderbuffer(&depth, FboAttachment::DepthStencil);
+
+        fbo.renderbuffers.extend([color, depth]);
+        fbo.check_status()?;
+        Ok(fbo)
+    }
+
+    /// Copies (and resolves, if this one is multisampled) color attachment 0 into `target`,
+    /// stretching it to `target`'s size. `filter` is gl::NEAREST or gl::LINEAR, resolving
+    /// needs both to be the same size. Errors if either Framebuffer is incomplete
+    pub fn blit_to(&self, target: &Framebuffer, filter: GLenum) -> Result<(), GlError> {
+        self.check_status()?;
+        target.check_status()?;
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::READ_FRAMEBUFFER, self.id);
+            GlStateCache::bind_framebuffer(gl::DRAW_FRAMEBUFFER, target.id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.width as GLint,
+                self.height as GLint,
+                0,
+                0,
+                target.width as GLint,
+                target.height as GLint,
+                gl::COLOR_BUFFER_BIT,
+                filter,
+            );
+            GlStateCache::bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+            GlStateCache::bind_framebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        }
+        Ok(())
+    }
+
+    /// Creates a Framebuffer with its own color texture of `format` at attachment 0
+    pub fn with_format(width: u32, height: u32, format: InternalFormat) -> Self {
+        let mut color = Texture2D::generate();
+        color.allocate(width, height, format);
+
+        let mut fbo = Self::generate(width, height);
+        fbo.attach(FboAttachment::Color(0), &color, 0);
+        fbo.color = Some(color);
+        fbo
+    }
+
+    /// Attaches `renderbuffer` at `attachment`, the renderbuffer is not owned by the Framebuffer
+    pub fn attach_renderbuffer(&self, renderbuffer: &Renderbuffer, attachment: FboAttachment) {
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                attachment.to_gl(),
+                gl::RENDERBUFFER,
+                renderbuffer.id(),
+            );
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Attaches mip `level` of `tex` at `attachment`, the texture is not owned by the Framebuffer
+    pub fn attach(&self, attachment: FboAttachment, tex: &Texture2D, level: u32) {
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                attachment.to_gl(),
+                gl::TEXTURE_2D,
+                tex.id(),
+                level as GLint,
+            );
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Makes fragment shader outputs 0..count write to color attachments 0..count (MRT)
+    pub fn set_draw_buffers(&self, count: u32) {
+        let buffers: Vec<GLenum> = (0..count).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            gl::DrawBuffers(buffers.len() as GLsizei, buffers.as_ptr());
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Attaches all six faces of `cubemap` as the (layered) depth attachment and disables
+    /// color output, a geometry shader then picks the face via gl_Layer
+    pub fn attach_depth_cubemap(&self, cubemap: &TextureCubemap) {
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            gl::FramebufferTexture(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, cubemap.id(), 0);
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Checks gl::CheckFramebufferStatus(..) and reports the status code if incomplete
+    pub fn check_status(&self) -> Result<(), GlError> {
+        let status = unsafe {
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+            status
+        };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(GlError::FramebufferIncomplete {
+                id: self.id,
+                status,
+            });
+        }
+        Ok(())
+    }
+
+    /// The color texture created by `Framebuffer::new` or `Framebuffer::with_format`
+    pub fn texture(&self) -> Option<&Texture2D> {
+        self.color.as_ref()
+    }
+
+    /// Reads the given color attachment back to the CPU as tightly packed RGBA8 rows
+    /// (bottom row first) and returns them together with the (width, height) of the FBO
+    pub fn read_color(&self, attachment: u32) -> (Vec<u8>, (u32, u32)) {
+        let mut pixels: Vec<u8> = vec![0; self.width as usize * self.height as usize * 4];
+
+        unsafe {
+            GlStateCache::bind_framebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment);
+            with_pixel_store(gl::PACK_ALIGNMENT, 1, || {
+                gl::ReadPixels(
+                    0,
+                    0,
+                    self.width as GLint,
+                    self.height as GLint,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_mut_ptr() as *mut GLvoid,
+                )
+            });
+            GlStateCache::bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+
+        (pixels, (self.width, self.height))
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn bind(&self) {
+        GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_framebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            GlStateCache::framebuffer_deleted(self.id);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        self.unbind();
+        self.delete();
+    }
+}
+
+/// Two Fbos of the same size for passes that read the previous result while writing the next
+pub struct PingPongFbo {
+    pub a: Framebuffer,
+    pub b: Framebuffer,
+    current: usize,
+}
+
+impl PingPongFbo {
+    pub fn new(width: u32, height: u32, format: InternalFormat) -> Self {
+        PingPongFbo {
+            a: Framebuffer::with_format(width, height, format),
+            b: Framebuffer::with_format(width, height, format),
+            current: 0,
+        }
+    }
+
+    /// The Framebuffer holding the latest result
+    pub fn read(&self) -> &Framebuffer {
+        if self.current == 0 { &self.a } else { &self.b }
+    }
+
+    /// The Framebuffer the next pass renders into
+    pub fn write(&self) -> &Framebuffer {
+        if self.current == 0 { &self.b } else { &self.a }
+    }
+
+    /// Call after each pass so the written Framebuffer becomes the one that's read
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+/// Uniform Object
 pub struct Uniform {
     pub id: GLint,
 }
 
-impl Uniform {
-    pub fn new(program: u32, name: &str) -> Result<Self, String> {
-        let cname = CString::new(name).expect("CString::new failed in Uniform Creation");
-        let location: GLint = unsafe { gl::GetUniformLocation(program, cname.as_ptr()) };
-        if location == -1 {
-            return Err(format!("Couldn't get Uniform location for {}", name));
+impl Uniform {
+    pub fn new(program: u32, name: &str) -> Result<Self, GlError> {
+        let cname = CString::new(name)?;
+        let location: GLint = unsafe { gl::GetUniformLocation(program, cname.as_ptr()) };
+        if location == -1 {
+            return Err(GlError::UniformNotFound {
+                name: name.to_string(),
+            });
+        }
+        Ok(Uniform { id: location })
+    }
+
+    /// For uniforms the shader may leave out or the compiler may optimize away, None if
+    /// `name` isn't active in `program`
+    pub fn try_new(program: u32, name: &str) -> Option<Self> {
+        Self::new(program, name).ok()
+    }
+
+    pub fn set_1f(&self, value: f32) {
+        unsafe {
+            gl::Uniform1f(self.id, value);
+        }
+    }
+
+    /// Uploads `values` to a `uniform float name[N]` array, starting at element 0
+    pub fn set_1fv(&self, values: &[f32]) {
+        unsafe {
+            gl::Uniform1fv(self.id, values.len() as GLint, values.as_ptr());
+        }
+    }
+
+    pub fn set_1i(&self, value: i32) {
+        unsafe {
+            gl::Uniform1i(self.id, value);
+        }
+    }
+
+    pub fn set_vec2f(&self, value: (f32, f32)) {
+        unsafe {
+            gl::Uniform2f(self.id, value.0, value.1);
+        }
+    }
+
+    pub fn set_vec3f(&self, value: (f32, f32, f32)) {
+        unsafe {
+            gl::Uniform3f(self.id, value.0, value.1, value.2);
+        }
+    }
+
+    pub fn set_vec4f(&self, value: (f32, f32, f32, f32)) {
+        unsafe {
+            gl::Uniform4f(self.id, value.0, value.1, value.2, value.3);
+        }
+    }
+
+    /// `value` is column-major unless `transpose` is set (e.g: glam's `to_cols_array()`)
+    pub fn set_mat3f(&self, value: &[f32; 9], transpose: bool) {
+        unsafe {
+            gl::UniformMatrix3fv(self.id, 1, transpose as GLboolean, value.as_ptr());
+        }
+    }
+
+    /// `value` is column-major unless `transpose` is set (e.g: glam's `to_cols_array()`)
+    pub fn set_mat4f(&self, value: &[f32; 16], transpose: bool) {
+        unsafe {
+            gl::UniformMatrix4fv(self.id, 1, transpose as GLboolean, value.as_ptr());
+        }
+    }
+}
+
+/// A value that can be written to a uniform location
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UniformValue {
+    Int(i32),
+    Float(f32),
+    Vec2(f32, f32),
+    Vec3(f32, f32, f32),
+    Vec4(f32, f32, f32, f32),
+    Mat4([f32; 16]),
+}
+
+impl UniformValue {
+    /// Writes the value to `location` of the current program
+    pub fn apply(&self, location: GLint) {
+        unsafe {
+            match *self {
+                UniformValue::Int(v) => gl::Uniform1i(location, v),
+                UniformValue::Float(v) => gl::Uniform1f(location, v),
+                UniformValue::Vec2(x, y) => gl::Uniform2f(location, x, y),
+                UniformValue::Vec3(x, y, z) => gl::Uniform3f(location, x, y, z),
+                UniformValue::Vec4(x, y, z, w) => gl::Uniform4f(location, x, y, z, w),
+                UniformValue::Mat4(m) => gl::UniformMatrix4fv(location, 1, gl::FALSE, m.as_ptr()),
+            }
+        }
+    }
+}
+
+/// Uniform updates queued from any thread, applied on the GL (main) thread by `flush`
+#[derive(Default)]
+pub struct PendingUniforms {
+    queue: Mutex<Vec<(GLint, UniformValue)>>,
+}
+
+impl PendingUniforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Can be called from any thread, nothing touches GL until `flush`
+    pub fn push(&self, location: GLint, value: UniformValue) {
+        self.queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((location, value));
+    }
+
+    /// Main thread only: makes `program` current and applies all queued updates in order
+    pub fn flush(&self, program: &Program) {
+        let pending = std::mem::take(&mut *self.queue.lock().unwrap_or_else(|e| e.into_inner()));
+        if pending.is_empty() {
+            return;
+        }
+
+        program.set();
+        for (location, value) in pending {
+            value.apply(location);
+        }
+    }
+}
+
+/// Uniform Buffer Object holding one `T`, lay `T` out like the std140 block it feeds
+/// (`#[repr(C)]`, vec3 padded to 16 bytes, ..)
+pub struct UniformBuffer<T: Copy> {
+    pub id: GLuint,
+    binding_point: u32,
+    _data: PhantomData<T>,
+}
+
+impl<T: Copy> UniformBuffer<T> {
+    /// Uploads `data` and binds the buffer to `binding_point`
+    pub fn new(data: &T, binding_point: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            GlStateCache::bind_buffer(gl::UNIFORM_BUFFER, id);
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                size_of::<T>() as GLsizeiptr,
+                data as *const T as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            GlStateCache::bind_buffer(gl::UNIFORM_BUFFER, 0);
+            GlStateCache::bind_buffer_base(gl::UNIFORM_BUFFER, binding_point, id);
+        }
+        UniformBuffer {
+            id,
+            binding_point,
+            _data: PhantomData,
+        }
+    }
+
+    /// Overwrites the whole buffer, e.g: once per frame
+    pub fn update(&self, data: &T) {
+        unsafe {
+            GlStateCache::bind_buffer(gl::UNIFORM_BUFFER, self.id);
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                size_of::<T>() as GLsizeiptr,
+                data as *const T as *const GLvoid,
+            );
+            GlStateCache::bind_buffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+
+    /// Connects the `uniform block_name { .. }` block of `program` to this buffer's binding point
+    pub fn bind_to_program(&self, program: &Program, block_name: &str) -> Result<(), GlError> {
+        let cname = CString::new(block_name)?;
+        let index = unsafe { gl::GetUniformBlockIndex(program.id(), cname.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            return Err(GlError::UniformNotFound {
+                name: block_name.to_string(),
+            });
+        }
+        unsafe {
+            gl::UniformBlockBinding(program.id(), index, self.binding_point);
+        }
+        Ok(())
+    }
+
+    pub fn binding_point(&self) -> u32 {
+        self.binding_point
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl<T: Copy> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
+        }
+    }
+}
+
+/// Shader Storage Buffer Object holding a slice of `T`, read (and written) by shaders through
+/// a `layout(std430, binding = N) buffer` block. Lay `T` out like std430 (`#[repr(C)]`,
+/// vec3 padded to 16 bytes, ..). Needs an OpenGL 4.3 context
+pub struct ShaderStorageBuffer<T: Copy> {
+    pub id: GLuint,
+    binding_point: u32,
+    /// Elements allocated by `new` or the last reallocating `update`
+    len: usize,
+    _data: PhantomData<T>,
+}
+
+impl<T: Copy> ShaderStorageBuffer<T> {
+    /// Uploads `data` and binds the buffer to `binding_point`
+    pub fn new(data: &[T], binding_point: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        let ssbo = ShaderStorageBuffer {
+            id,
+            binding_point,
+            len: data.len(),
+            _data: PhantomData,
+        };
+        ssbo.allocate(data);
+        ssbo.bind();
+        ssbo
+    }
+
+    /// Overwrites the buffer in place if `data` has the same length, reallocates it otherwise
+    pub fn update(&mut self, data: &[T]) {
+        if data.len() != self.len {
+            self.allocate(data);
+            self.len = data.len();
+            return;
+        }
+        unsafe {
+            GlStateCache::bind_buffer(gl::SHADER_STORAGE_BUFFER, self.id);
+            gl::BufferSubData(
+                gl::SHADER_STORAGE_BUFFER,
+                0,
+                size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const GLvoid,
+            );
+            GlStateCache::bind_buffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+    }
+
+    fn allocate(&self, data: &[T]) {
+        unsafe {
+            GlStateCache::bind_buffer(gl::SHADER_STORAGE_BUFFER, self.id);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            GlStateCache::bind_buffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+    }
+
+    /// (Re)binds the buffer to its binding point, e.g: after another buffer took it
+    pub fn bind(&self) {
+        GlStateCache::bind_buffer_base(gl::SHADER_STORAGE_BUFFER, self.binding_point, self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_buffer_base(gl::SHADER_STORAGE_BUFFER, self.binding_point, 0);
+    }
+
+    /// Elements in the buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn binding_point(&self) -> u32 {
+        self.binding_point
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl<T: Copy> Drop for ShaderStorageBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
         }
-        Ok(Uniform { id: location })
     }
+}
 
-    pub fn set_1f(&self, value: f32) {
+/// One draw of gl::MultiDrawElementsIndirect(..), laid out like GL's
+/// DrawElementsIndirectCommand
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrawCommand {
+    /// Indices to draw
+    pub count: u32,
+    pub instance_count: u32,
+    /// First index to draw, in indices not bytes
+    pub first_index: u32,
+    /// Added to every index before fetching the vertex
+    pub base_vertex: i32,
+    /// First instance, offsets the per-instance attributes
+    pub base_instance: u32,
+}
+
+/// gl::DRAW_INDIRECT_BUFFER of DrawCommands, drawn in one call with
+/// `Mesh::multi_draw_indirect`. Needs an OpenGL 4.3 context
+pub struct IndirectBuffer {
+    pub id: GLuint,
+    /// Commands allocated by `new` or the last reallocating `update`
+    len: usize,
+}
+
+impl IndirectBuffer {
+    pub fn new(commands: &[DrawCommand]) -> Self {
+        let mut id: GLuint = 0;
         unsafe {
-            gl::Uniform1f(self.id, value);
+            gl::GenBuffers(1, &mut id);
         }
+        let buffer = IndirectBuffer {
+            id,
+            len: commands.len(),
+        };
+        buffer.allocate(commands);
+        buffer
     }
 
-    pub fn set_vec2f(&self, value: (f32, f32)) {
+    /// Overwrites the buffer in place if `commands` has the same length, reallocates it otherwise
+    pub fn update(&mut self, commands: &[DrawCommand]) {
+        if commands.len() != self.len {
+            self.allocate(commands);
+            self.len = commands.len();
+            return;
+        }
         unsafe {
-            gl::Uniform2f(self.id, value.0, value.1);
+            GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, self.id);
+            gl::BufferSubData(
+                gl::DRAW_INDIRECT_BUFFER,
+                0,
+                size_of_val(commands) as GLsizeiptr,
+                commands.as_ptr() as *const GLvoid,
+            );
+            GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+    }
+
+    fn allocate(&self, commands: &[DrawCommand]) {
+        unsafe {
+            GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, self.id);
+            gl::BufferData(
+                gl::DRAW_INDIRECT_BUFFER,
+                size_of_val(commands) as GLsizeiptr,
+                commands.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+    }
+
+    pub fn bind(&self) {
+        GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, self.id);
+    }
+
+    pub fn unbind(&self) {
+        GlStateCache::bind_buffer(gl::DRAW_INDIRECT_BUFFER, 0);
+    }
+
+    /// Commands in the buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for IndirectBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
+        }
+    }
+}
+
+/// gl::PIXEL_PACK_BUFFER that gl::ReadPixels(..) copies into without waiting for the GPU,
+/// e.g: `start_readback` this frame and `map_read` a frame or two later (or after a
+/// FenceSync inserted behind it signaled), mapping earlier stalls like a plain ReadPixels
+pub struct PixelBuffer {
+    pub id: GLuint,
+    size: usize,
+}
+
+impl PixelBuffer {
+    /// `byte_size` is usually width * height * 4 for the RGBA8 readback
+    pub fn new_pack(byte_size: usize) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, id);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                byte_size as GLsizeiptr,
+                null(),
+                gl::STREAM_READ,
+            );
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        PixelBuffer {
+            id,
+            size: byte_size,
+        }
+    }
+
+    /// Queues a copy of the RGBA8 pixels in the rectangle of the bound read framebuffer,
+    /// rows bottom first like gl::ReadPixels(..). Returns right away
+    pub fn start_readback(&self, x: i32, y: i32, width: u32, height: u32) {
+        debug_assert!(
+            width as usize * height as usize * 4 <= self.size,
+            "A {}x{} readback doesn't fit into the {} bytes of PixelBuffer {}",
+            width,
+            height,
+            self.size,
+            self.id
+        );
+        unsafe {
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, self.id);
+            with_pixel_store(gl::PACK_ALIGNMENT, 1, || {
+                gl::ReadPixels(
+                    x,
+                    y,
+                    width as GLint,
+                    height as GLint,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    null_mut(),
+                )
+            });
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+    }
+
+    /// The whole buffer, None if mapping failed. Blocks until the last `start_readback`
+    /// is done
+    pub fn map_read(&self) -> Option<PixelBufferMap<'_>> {
+        let ptr = unsafe {
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, self.id);
+            let ptr = gl::MapBuffer(gl::PIXEL_PACK_BUFFER, gl::READ_ONLY);
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+            ptr
+        };
+        if ptr.is_null() {
+            return None;
+        }
+
+        Some(PixelBufferMap {
+            id: self.id,
+            ptr: ptr as *const u8,
+            len: self.size,
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Bytes allocated by `new_pack`
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for PixelBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+            GlStateCache::buffer_deleted(self.id);
+        }
+    }
+}
+
+/// Read only PixelBuffer memory from `PixelBuffer::map_read`, unmapped again when dropped
+pub struct PixelBufferMap<'a> {
+    id: GLuint,
+    ptr: *const u8,
+    len: usize,
+    _buffer: PhantomData<&'a [u8]>,
+}
+
+impl Deref for PixelBufferMap<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for PixelBufferMap<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, self.id);
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            GlStateCache::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+    }
+}
+
+/// A query object of one `target`, e.g: gl::TIME_ELAPSED. Queries of a target can't nest,
+/// only one may be between begin and end at a time
+struct Query {
+    id: GLuint,
+    target: GLenum,
+}
+
+impl Query {
+    fn generate(target: GLenum) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenQueries(1, &mut id);
+        }
+        Query { id, target }
+    }
+
+    fn begin(&self) {
+        unsafe {
+            gl::BeginQuery(self.target, self.id);
+        }
+    }
+
+    fn end(&self) {
+        unsafe {
+            gl::EndQuery(self.target);
+        }
+    }
+
+    /// None while the GPU hasn't got there yet
+    fn result(&self) -> Option<u64> {
+        let mut available: GLint = 0;
+        unsafe {
+            gl::GetQueryObjectiv(self.id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        if available == gl::FALSE as GLint {
+            return None;
+        }
+
+        let mut result: u64 = 0;
+        unsafe {
+            gl::GetQueryObjectui64v(self.id, gl::QUERY_RESULT, &mut result);
+        }
+        Some(result)
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteQueries(1, &self.id);
+        }
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+/// GPU time between `begin` and `end`. Queries can't nest, only one TimerQuery may
+/// be between begin and end at a time
+pub struct TimerQuery {
+    query: Query,
+}
+
+impl TimerQuery {
+    pub fn generate() -> Self {
+        TimerQuery {
+            query: Query::generate(gl::TIME_ELAPSED),
+        }
+    }
+
+    pub fn begin(&self) {
+        self.query.begin();
+    }
+
+    pub fn end(&self) {
+        self.query.end();
+    }
+
+    /// Nanoseconds the GPU spent between `begin` and `end`. None while the GPU hasn't got
+    /// there yet, usually a frame or two later, poll again instead of stalling on it
+    pub fn result_ns(&self) -> Option<u64> {
+        self.query.result()
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.query.id
+    }
+}
+
+/// Counts the samples that pass the depth test between `begin` and `end`, e.g: draw a
+/// bounding box with color and depth writes off to find out if an object is hidden behind
+/// the event horizon. Like TimerQuery only one may be between begin and end at a time
+pub struct OcclusionQuery {
+    query: Query,
+}
+
+impl OcclusionQuery {
+    pub fn generate() -> Self {
+        OcclusionQuery {
+            query: Query::generate(gl::SAMPLES_PASSED),
+        }
+    }
+
+    pub fn begin(&self) {
+        self.query.begin();
+    }
+
+    pub fn end(&self) {
+        self.query.end();
+    }
+
+    /// Samples that passed, None while the GPU hasn't got there yet. Usually read a frame
+    /// later, or let the GPU decide with `begin_conditional_render` instead
+    pub fn result(&self) -> Option<u64> {
+        self.query.result()
+    }
+
+    /// Draws until `end_conditional_render` are skipped by the GPU if no sample passed.
+    /// `mode` is e.g: gl::QUERY_WAIT or gl::QUERY_NO_WAIT (draw anyway if not ready yet)
+    pub fn begin_conditional_render(&self, mode: GLenum) {
+        unsafe {
+            gl::BeginConditionalRender(self.query.id, mode);
+        }
+    }
+
+    pub fn end_conditional_render(&self) {
+        unsafe {
+            gl::EndConditionalRender();
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.query.id
+    }
+}
+
+/// What `FenceSync::client_wait` returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncResult {
+    /// The GPU was already past the fence when waiting started
+    AlreadySignaled,
+    /// The GPU got past the fence while waiting
+    ConditionSatisfied,
+    TimeoutExpired,
+    /// gl::WAIT_FAILED, e.g: the context was lost
+    WaitFailed,
+}
+
+/// GPU fence, signaled once every command issued before `insert` is done. e.g: insert one
+/// after drawing from a persistently mapped Vbo and `client_wait` on it before writing
+/// that region again
+pub struct FenceSync {
+    sync: GLsync,
+}
+
+impl FenceSync {
+    pub fn insert() -> Self {
+        let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        FenceSync { sync }
+    }
+
+    /// Blocks the CPU for at most `timeout_ns`, 0 only polls. Pending commands are flushed
+    /// so the fence is guaranteed to signal eventually
+    pub fn client_wait(&self, timeout_ns: u64) -> SyncResult {
+        let status =
+            unsafe { gl::ClientWaitSync(self.sync, gl::SYNC_FLUSH_COMMANDS_BIT, timeout_ns) };
+        match status {
+            gl::ALREADY_SIGNALED => SyncResult::AlreadySignaled,
+            gl::CONDITION_SATISFIED => SyncResult::ConditionSatisfied,
+            gl::TIMEOUT_EXPIRED => SyncResult::TimeoutExpired,
+            _ => SyncResult::WaitFailed,
+        }
+    }
+
+    /// Makes the GPU, not the CPU, wait for the fence before running later commands.
+    /// Returns right away
+    pub fn wait(&self) {
+        unsafe {
+            gl::WaitSync(self.sync, 0, gl::TIMEOUT_IGNORED);
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteSync(self.sync);
+        }
+    }
+}
+
+impl Drop for FenceSync {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::create_gl_context;
+
+    const VERT: &str = "#version 330 core\nlayout(location = 0) in vec2 a_pos;\nvoid main() { gl_Position = vec4(a_pos, 0.0, 1.0); }\n";
+    const FRAG: &str = "#version 330 core\nuniform float u_value;\nout vec4 FragColor;\nvoid main() { FragColor = vec4(u_value); }\n";
+
+    fn shader(source: &str, kind: GLenum) -> Result<Shader, GlError> {
+        Shader::from_source_str(source, kind)
+    }
+
+    /// Fresh directory under the system temp dir, filled with `files` (path, contents)
+    fn temp_dir_with(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("opengl_blackhole_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
         }
+        dir
+    }
+
+    #[test]
+    fn resolve_includes_nests_relative_to_the_including_file() {
+        let dir = temp_dir_with(
+            "includes_nested",
+            &[
+                ("common/a.glsl", "#include \"b.glsl\"\nfloat a;"),
+                ("common/b.glsl", "float b;"),
+                ("shared.glsl", "float shared;"),
+            ],
+        );
+        let source = "#include \"common/a.glsl\"\n#include \"shared.glsl\"\n#include \"shared.glsl\"\nvoid main() {}";
+        let result = resolve_includes_with_files(source, &dir);
+        let canonical = |path: &str| std::fs::canonicalize(dir.join(path)).unwrap();
+        let expected_files = vec![
+            canonical("common/a.glsl"),
+            canonical("common/b.glsl"),
+            canonical("shared.glsl"),
+            canonical("shared.glsl"),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (resolved, files) = result.unwrap();
+        assert_eq!(
+            resolved,
+            "float b;\n\nfloat a;\n\nfloat shared;\n\nfloat shared;\n\nvoid main() {}\n"
+        );
+        assert_eq!(files, expected_files);
+    }
+
+    #[test]
+    fn resolve_includes_reports_cycles() {
+        let dir = temp_dir_with(
+            "includes_cycle",
+            &[
+                ("self.glsl", "#include \"self.glsl\""),
+                ("x.glsl", "#include \"sub/y.glsl\""),
+                ("sub/y.glsl", "#include \"../x.glsl\""),
+            ],
+        );
+        let direct = resolve_includes("#include \"self.glsl\"", &dir);
+        let indirect = resolve_includes("#include \"x.glsl\"", &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        match direct {
+            Err(GlError::IncludeCycle(chain)) => assert_eq!(chain.len(), 2),
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+        match indirect {
+            Err(GlError::IncludeCycle(chain)) => {
+                assert_eq!(chain.len(), 3);
+                assert_eq!(chain.first(), chain.last());
+            }
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+    }
+
+    fn uniform(name: &str, type_str: &str, array_size: Option<u32>) -> GlslUniformDecl {
+        GlslUniformDecl {
+            name: name.to_string(),
+            type_str: type_str.to_string(),
+            array_size,
+        }
+    }
+
+    #[test]
+    fn parse_glsl_uniforms_reads_arrays_and_declarator_lists() {
+        let source = "#version 330 core\n\
+                      uniform vec2 u_resolution;\n\
+                      uniform highp float u_time, u_scale;\n\
+                      uniform vec3 u_lights[4], u_color;\n\
+                      uniform float u_weights[KERNEL_SIZE];\n\
+                      uniform mat4 u_view; uniform mat4 u_proj;\n\
+                      in vec2 v_uv;\n";
+        assert_eq!(
+            parse_glsl_uniforms(source),
+            vec![
+                uniform("u_resolution", "vec2", None),
+                uniform("u_time", "float", None),
+                uniform("u_scale", "float", None),
+                uniform("u_lights", "vec3", Some(4)),
+                uniform("u_color", "vec3", None),
+                uniform("u_weights", "float", None),
+                uniform("u_view", "mat4", None),
+                uniform("u_proj", "mat4", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_glsl_uniforms_skips_comments() {
+        let source = "// uniform float u_line;\n\
+                      uniform float u_kept; // uniform float u_trailing;\n\
+                      /* uniform float u_block; */ uniform int u_after_block;\n\
+                      /*\n\
+                      uniform float u_multiline;\n\
+                      */\n\
+                      uniform vec2 u_last; // see /* not a block comment\n\
+                      uniform vec3 u_end;\n";
+        assert_eq!(
+            parse_glsl_uniforms(source),
+            vec![
+                uniform("u_kept", "float", None),
+                uniform("u_after_block", "int", None),
+                uniform("u_last", "vec2", None),
+                uniform("u_end", "vec3", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_glsl_uniforms_ignores_initializers() {
+        let source = "uniform float u_exposure = 1.0;\n\
+                      uniform vec3 u_tint = vec3(1.0, 0.5, 0.25), u_fog;\n\
+                      uniform int u_steps[2] = int[2](8, 16);\n";
+        assert_eq!(
+            parse_glsl_uniforms(source),
+            vec![
+                uniform("u_exposure", "float", None),
+                uniform("u_tint", "vec3", None),
+                uniform("u_fog", "vec3", None),
+                uniform("u_steps", "int", Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_glsl_uniforms_skips_blocks() {
+        let source = "uniform Camera {\n\
+                      mat4 u_view;\n\
+                      mat4 u_proj;\n\
+                      } camera;\n\
+                      layout(std140) uniform Lights { vec4 u_lights[8]; };\n\
+                      uniform float u_time;\n";
+        assert_eq!(
+            parse_glsl_uniforms(source),
+            vec![uniform("u_time", "float", None)]
+        );
+    }
+
+    #[test]
+    fn parse_glsl_uniforms_skips_layout_qualifiers() {
+        let source = "layout(location = 0) uniform float u_time;\n\
+                      layout (binding = 1) uniform sampler2D u_noise, u_sky;\n";
+        assert_eq!(
+            parse_glsl_uniforms(source),
+            vec![
+                uniform("u_time", "float", None),
+                uniform("u_noise", "sampler2D", None),
+                uniform("u_sky", "sampler2D", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn program_builder_needs_a_vertex_shader() {
+        // Fails before any GL call, no context needed
+        let errors = ProgramBuilder::new()
+            .build()
+            .err()
+            .expect("nothing is attached");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Program has no vertex shader");
+    }
+
+    #[test]
+    #[ignore = "needs a display"]
+    fn shader_compiles() {
+        let _context = create_gl_context();
+        assert!(shader(VERT, gl::VERTEX_SHADER).is_ok());
+        assert!(shader(FRAG, gl::FRAGMENT_SHADER).is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs a display"]
+    fn shader_reports_compile_errors() {
+        let _context = create_gl_context();
+        match shader(
+            "#version 330 core\nvoid main() { oops }\n",
+            gl::VERTEX_SHADER,
+        ) {
+            Err(GlError::ShaderCompile { kind, log }) => {
+                assert_eq!(kind, gl::VERTEX_SHADER);
+                assert!(!log.trim().is_empty());
+            }
+            other => panic!("expected a compile error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a display"]
+    fn program_links_and_uniforms_resolve() {
+        let _context = create_gl_context();
+        let program = Program::from_shaders(&[
+            shader(VERT, gl::VERTEX_SHADER).unwrap(),
+            shader(FRAG, gl::FRAGMENT_SHADER).unwrap(),
+        ])
+        .unwrap();
+
+        assert!(Uniform::new(program.id(), "u_value").is_ok());
+        assert!(matches!(
+            Uniform::new(program.id(), "u_missing"),
+            Err(GlError::UniformNotFound { .. })
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a display"]
+    fn program_builder_reports_every_broken_stage() {
+        let _context = create_gl_context();
+        let broken = "#version 330 core\nvoid main() { oops }\n";
+        let errors = ProgramBuilder::new()
+            .attach_vert(broken)
+            .attach_frag(broken)
+            .build()
+            .err()
+            .expect("both stages are broken");
+
+        assert_eq!(errors.len(), 2);
+
+        let errors = ProgramBuilder::new()
+            .attach_frag(FRAG)
+            .build()
+            .err()
+            .expect("there's no vertex shader");
+        assert!(matches!(
+            errors[..],
+            [GlError::MissingStage {
+                kind: gl::VERTEX_SHADER
+            }]
+        ));
+        assert!(
+            ProgramBuilder::new()
+                .attach_vert(VERT)
+                .attach_frag(FRAG)
+                .build()
+                .is_ok()
+        );
     }
 }
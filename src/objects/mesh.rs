@@ -0,0 +1,212 @@
+use std::{collections::HashMap, error::Error};
+
+use glam::Vec3;
+
+use super::Vertex3D;
+
+/// Axis aligned cube centered at the origin. Every face has its own 4 vertices so normals
+/// stay flat and each face gets the full [0,1]² UV range (24 vertices, 36 indices)
+pub fn cube(half_size: f32) -> (Vec<Vertex3D>, Vec<u32>) {
+    // (normal, u axis, v axis) with u x v == normal so the faces wind counter-clockwise
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    for (normal, u, v) in faces {
+        let base = vertices.len() as u32;
+
+        for (cu, cv) in corners {
+            let position = (normal + u * cu + v * cv) * half_size;
+            vertices.push(Vertex3D::new(
+                position.into(),
+                (1.0, 1.0, 1.0),
+                ((cu + 1.0) * 0.5, (cv + 1.0) * 0.5),
+                normal.into(),
+            ));
+        }
+
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertices, indices)
+}
+
+/// A material parsed from a Wavefront MTL file, texture paths are relative to the MTL file
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// Ambient color (Ka)
+    pub ambient: [f32; 3],
+    /// Diffuse color (Kd)
+    pub diffuse: [f32; 3],
+    /// Specular color (Ks)
+    pub specular: [f32; 3],
+    /// Specular exponent (Ns)
+    pub shininess: f32,
+    /// map_Kd
+    pub diffuse_map: Option<String>,
+    /// map_bump / bump
+    pub bump_map: Option<String>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        MtlMaterial {
+            name: name.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            diffuse_map: None,
+            bump_map: None,
+        }
+    }
+}
+
+/// Parses the `newmtl` blocks of an MTL file keyed by material name.
+/// Only Ka, Kd, Ks, Ns, map_Kd and map_bump are read, everything else is ignored
+pub fn load_mtl(path: &str) -> Result<HashMap<String, MtlMaterial>, Box<dyn Error>> {
+    let source = std::fs::read_to_string(path)?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((keyword, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let rest = rest.trim();
+
+        if keyword == "newmtl" {
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            current = Some(MtlMaterial::new(rest));
+            continue;
+        }
+
+        let Some(material) = current.as_mut() else {
+            return Err(format!("{}:{}: {} before any newmtl", path, number + 1, keyword).into());
+        };
+
+        let color = || -> Result<[f32; 3], Box<dyn Error>> {
+            let values = rest
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+            match values[..] {
+                [r, g, b] => Ok([r, g, b]),
+                // A single value sets all three channels
+                [v] => Ok([v; 3]),
+                _ => Err(
+                    format!("{}:{}: expected 3 values for {}", path, number + 1, keyword).into(),
+                ),
+            }
+        };
+
+        match keyword {
+            "Ka" => material.ambient = color()?,
+            "Kd" => material.diffuse = color()?,
+            "Ks" => material.specular = color()?,
+            "Ns" => {
+                material.shininess = rest
+                    .parse()
+                    .map_err(|e| format!("{}:{}: {}", path, number + 1, e))?
+            }
+            // Options like `-bm 1.0` come before the file name
+            "map_Kd" => material.diffuse_map = rest.split_whitespace().last().map(String::from),
+            "map_bump" | "bump" => {
+                material.bump_map = rest.split_whitespace().last().map(String::from)
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current {
+        materials.insert(material.name.clone(), material);
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `source` to a temporary MTL file and loads it
+    fn load(name: &str, source: &str) -> Result<HashMap<String, MtlMaterial>, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "opengl_blackhole_{}_{}.mtl",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, source).unwrap();
+        let materials = load_mtl(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        materials
+    }
+
+    #[test]
+    fn load_mtl_reads_every_material() {
+        let materials = load(
+            "materials",
+            "# Exported by hand\n\
+             newmtl disk\n\
+             Ka 0.1 0.2 0.3\n\
+             Kd 0.5   # a single value is gray\n\
+             Ks 1 1 1\n\
+             Ns 32\n\
+             map_Kd -bm 1.0 disk.png\n\
+             illum 2\n\
+             \n\
+             newmtl jet\n\
+             Kd 0.2 0.4 1.0\n\
+             bump -bm 0.5 jet_normal.png\n",
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 2);
+
+        assert_eq!(
+            materials["disk"],
+            MtlMaterial {
+                name: "disk".to_string(),
+                ambient: [0.1, 0.2, 0.3],
+                diffuse: [0.5; 3],
+                specular: [1.0; 3],
+                shininess: 32.0,
+                diffuse_map: Some("disk.png".to_string()),
+                bump_map: None,
+            }
+        );
+        let jet = &materials["jet"];
+        assert_eq!(jet.diffuse, [0.2, 0.4, 1.0]);
+        assert_eq!(jet.ambient, [0.0; 3]);
+        assert_eq!(jet.diffuse_map, None);
+        assert_eq!(jet.bump_map.as_deref(), Some("jet_normal.png"));
+    }
+
+    #[test]
+    fn load_mtl_rejects_bad_lines() {
+        let error = load("no_newmtl", "# comment\nKd 1 0 0\nnewmtl late\n").unwrap_err();
+        assert!(
+            error.to_string().contains(":2: Kd before any newmtl"),
+            "{}",
+            error
+        );
+
+        assert!(load("two_values", "newmtl a\nKd 1 0\n").is_err());
+        assert!(load("not_a_number", "newmtl a\nNs shiny\n").is_err());
+        assert!(load("missing", "").unwrap().is_empty());
+    }
+}
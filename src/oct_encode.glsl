@@ -0,0 +1,16 @@
+// Octahedral encoding of a unit normal into [-1,1]², mirrors math::oct_encode
+vec2 oct_sign_not_zero(vec2 v) {
+  return vec2(v.x >= 0.0 ? 1.0 : -1.0, v.y >= 0.0 ? 1.0 : -1.0);
+}
+
+vec2 oct_encode(vec3 n) {
+  float l1 = abs(n.x) + abs(n.y) + abs(n.z);
+  if (l1 == 0.0) {
+    return vec2(0.0);
+  }
+  vec2 p = n.xy / l1;
+  if (n.z < 0.0) {
+    p = (1.0 - abs(p.yx)) * oct_sign_not_zero(p);
+  }
+  return p;
+}
@@ -0,0 +1,9 @@
+// Omnidirectional shadow lookup into a PointShadowMap depth cube-map.
+// Returns 1.0 when lit and 0.0 when in shadow
+float point_shadow(samplerCube shadow_map, vec3 world_pos, vec3 light_pos, float far_plane, float bias) {
+  vec3 frag_to_light = world_pos - light_pos;
+  // texture() is the core profile name of textureCube()
+  float closest = texture(shadow_map, frag_to_light).r * far_plane;
+
+  return length(frag_to_light) - bias > closest ? 0.0 : 1.0;
+}
@@ -0,0 +1,11 @@
+#version 330 core
+
+uniform vec3 u_light_pos;
+uniform float u_far;
+
+in vec3 vWorldPos;
+
+void main() {
+  // Linear distance to the light in [0,1] instead of the projected depth
+  gl_FragDepth = length(vWorldPos - u_light_pos) / u_far;
+}
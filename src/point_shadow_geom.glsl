@@ -0,0 +1,21 @@
+#version 330 core
+
+layout(triangles) in;
+layout(triangle_strip, max_vertices = 18) out;
+
+// View projection per cube face, in GL_TEXTURE_CUBE_MAP_POSITIVE_X .. NEGATIVE_Z order
+uniform mat4 u_shadow_matrices[6];
+
+out vec3 vWorldPos;
+
+void main() {
+  for (int face = 0; face < 6; face++) {
+    gl_Layer = face;
+    for (int i = 0; i < 3; i++) {
+      vWorldPos = gl_in[i].gl_Position.xyz;
+      gl_Position = u_shadow_matrices[face] * gl_in[i].gl_Position;
+      EmitVertex();
+    }
+    EndPrimitive();
+  }
+}
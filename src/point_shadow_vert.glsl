@@ -0,0 +1,10 @@
+#version 330 core
+
+layout(location = 0) in vec3 Position;
+
+uniform mat4 u_model;
+
+void main() {
+  // World space, the geometry shader projects into each cube face
+  gl_Position = u_model * vec4(Position, 1.0);
+}
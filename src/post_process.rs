@@ -0,0 +1,158 @@
+use std::{error::Error, ffi::CString};
+
+use gl::types::{GLint, GLuint};
+use glam::Mat4;
+
+use crate::{
+    objects::{
+        InternalFormat, PingPongFbo, Program, Texture2D, Uniform, Vao, create_program_from_str,
+    },
+    render_state::ProgramGuard,
+};
+
+/// Has to match MAX_RADIUS in blur_frag.glsl
+pub const MAX_BLUR_RADIUS: u32 = 32;
+
+/// Separable Gaussian blur (bloom, DoF) alternating horizontal and vertical passes
+pub struct GaussianBlurPass {
+    pub radius: u32,
+    pub sigma: f32,
+    pub ping_pong: PingPongFbo,
+    program: Program,
+    vao: Vao,
+    u_direction: Uniform,
+    u_radius: Uniform,
+    u_weights: Uniform,
+}
+
+impl GaussianBlurPass {
+    pub fn new(
+        width: u32,
+        height: u32,
+        radius: u32,
+        sigma: f32,
+        format: InternalFormat,
+    ) -> Result<Self, Box<dyn Error>> {
+        let program = create_program_from_str(
+            include_str!("fullscreen_vert.glsl"),
+            include_str!("blur_frag.glsl"),
+        )?;
+
+        Ok(GaussianBlurPass {
+            radius: radius.min(MAX_BLUR_RADIUS),
+            sigma,
+            ping_pong: PingPongFbo::new(width, height, format),
+            u_direction: Uniform::new(program.id(), "u_direction")?,
+            u_radius: Uniform::new(program.id(), "u_radius")?,
+            u_weights: Uniform::new(program.id(), "u_weights")?,
+            program,
+            vao: Vao::generate(),
+        })
+    }
+
+    /// Normalized one-sided kernel, `kernel()[0]` is the center weight
+    pub fn kernel(&self) -> Vec<f32> {
+        let radius = self.radius.min(MAX_BLUR_RADIUS);
+        let sigma = self.sigma.max(f32::EPSILON);
+
+        let mut weights: Vec<f32> = (0..=radius)
+            .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+
+        let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+        weights.iter_mut().for_each(|w| *w /= total);
+        weights
+    }
+
+    /// Runs `iterations / 2` horizontal and as many vertical passes, returns the blurred texture
+    pub fn blur<'a>(&'a mut self, input: &'a Texture2D, iterations: u32) -> &'a Texture2D {
+        let (width, height) = self.ping_pong.read().size();
+
+        let _guard = ProgramGuard::save_with_viewport();
+        unsafe {
+            gl::Viewport(0, 0, width as GLint, height as GLint);
+        }
+
+        self.program.set();
+        self.u_radius
+            .set_1i(self.radius.min(MAX_BLUR_RADIUS) as i32);
+        self.u_weights.set_1fv(&self.kernel());
+        self.vao.bind();
+
+        let passes = (iterations / 2) * 2;
+        for pass in 0..passes {
+            let direction = if pass % 2 == 0 {
+                (1.0 / width as f32, 0.0)
+            } else {
+                (0.0, 1.0 / height as f32)
+            };
+            self.u_direction.set_vec2f(direction);
+
+            let source = if pass == 0 {
+                input
+            } else {
+                self.ping_pong.read().texture().unwrap()
+            };
+            source.bind(0);
+
+            self.ping_pong.write().bind();
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+            self.ping_pong.swap();
+        }
+
+        self.ping_pong.read().unbind();
+
+        if passes == 0 {
+            return input;
+        }
+        self.ping_pong.read().texture().unwrap()
+    }
+}
+
+/// GLSL for `linearize_depth(depth)` and `reconstruct_position(uv, depth)`, paste it into a
+/// fragment shader after the `#version` line or `#include "depth_reconstruct.glsl"`
+pub const DEPTH_RECONSTRUCTION_GLSL: &str = include_str!("depth_reconstruct.glsl");
+
+/// Camera parameters needed to turn depth buffer values back into view-space positions
+#[derive(Clone, Copy, Debug)]
+pub struct DepthReconstruction {
+    pub near: f32,
+    pub far: f32,
+    inv_proj: Mat4,
+}
+
+impl DepthReconstruction {
+    /// `fov_y` is in radians, matches an OpenGL style right handed perspective projection
+    pub fn new(near: f32, far: f32, fov_y: f32, aspect: f32) -> Self {
+        let proj = Mat4::perspective_rh_gl(fov_y, aspect, near, far);
+        DepthReconstruction {
+            near,
+            far,
+            inv_proj: proj.inverse(),
+        }
+    }
+
+    /// Sets the uniforms of `depth_reconstruct.glsl` on the (current) program,
+    /// uniforms the shader doesn't use are skipped
+    pub fn upload(&self, program_id: GLuint) {
+        let location = |name: &str| {
+            let cname =
+                CString::new(name).expect("CString::new failed in DepthReconstruction upload");
+            unsafe { gl::GetUniformLocation(program_id, cname.as_ptr()) }
+        };
+
+        let scale = (self.inv_proj.x_axis.x, self.inv_proj.y_axis.y);
+        let row2 = self.inv_proj.row(2).to_array();
+        let row3 = self.inv_proj.row(3).to_array();
+
+        unsafe {
+            gl::Uniform1f(location("u_near"), self.near);
+            gl::Uniform1f(location("u_far"), self.far);
+            gl::Uniform2f(location("u_inv_proj_scale"), scale.0, scale.1);
+            gl::Uniform4fv(location("u_inv_proj_row2"), 1, row2.as_ptr());
+            gl::Uniform4fv(location("u_inv_proj_row3"), 1, row3.as_ptr());
+        }
+    }
+}
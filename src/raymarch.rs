@@ -0,0 +1,60 @@
+use std::ffi::CString;
+
+use gl::types::GLuint;
+
+/// Quality settings of the raymarching loop in the fragment shader
+#[derive(Clone, Copy, Debug)]
+pub struct RaymarchParams {
+    pub max_steps: u32,
+    pub max_distance: f32,
+    pub step_size: f32,
+}
+
+impl RaymarchParams {
+    /// Cheap settings for interactive use
+    pub fn preview() -> Self {
+        RaymarchParams {
+            max_steps: 64,
+            max_distance: 100.0,
+            step_size: 0.1,
+        }
+    }
+
+    /// Expensive settings for recordings and screenshots
+    pub fn final_capture() -> Self {
+        RaymarchParams {
+            max_steps: 512,
+            max_distance: 100.0,
+            step_size: 0.02,
+        }
+    }
+
+    pub fn increase_steps(&mut self, amount: u32) {
+        self.max_steps = self.max_steps.saturating_add(amount);
+    }
+
+    pub fn decrease_steps(&mut self, amount: u32) {
+        self.max_steps = self.max_steps.saturating_sub(amount).max(1);
+    }
+
+    /// Sets `u_max_steps`, `u_max_distance` and `u_step_size` on the (current) program,
+    /// uniforms the shader doesn't use are skipped
+    pub fn upload(&self, program_id: GLuint) {
+        let location = |name: &str| {
+            let cname = CString::new(name).expect("CString::new failed in RaymarchParams upload");
+            unsafe { gl::GetUniformLocation(program_id, cname.as_ptr()) }
+        };
+
+        unsafe {
+            gl::Uniform1i(location("u_max_steps"), self.max_steps as i32);
+            gl::Uniform1f(location("u_max_distance"), self.max_distance);
+            gl::Uniform1f(location("u_step_size"), self.step_size);
+        }
+    }
+}
+
+impl Default for RaymarchParams {
+    fn default() -> Self {
+        Self::preview()
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::objects::Framebuffer;
+
+/// Dumps every N-th frame of a Framebuffer as raw RGBA bytes, to be composed into a video with ffmpeg
+pub struct Recorder {
+    pub record: bool,
+    pub frame_interval: u32,
+    width: u32,
+    height: u32,
+    output_dir: PathBuf,
+    frame: u32,
+    written: u32,
+    recording: bool,
+}
+
+impl Recorder {
+    pub fn new(width: u32, height: u32, output_dir: &str) -> Self {
+        Recorder {
+            record: false,
+            frame_interval: 1,
+            width,
+            height,
+            output_dir: PathBuf::from(output_dir),
+            frame: 0,
+            written: 0,
+            recording: false,
+        }
+    }
+
+    /// Call once per frame after rendering into `fbo`, does nothing unless `record` is set
+    pub fn capture(&mut self, fbo: &Framebuffer) -> io::Result<()> {
+        if !self.record {
+            if self.recording {
+                self.finish();
+            }
+            return Ok(());
+        }
+
+        if !self.recording {
+            fs::create_dir_all(&self.output_dir)?;
+            self.recording = true;
+            self.frame = 0;
+            self.written = 0;
+        }
+
+        let frame = self.frame;
+        self.frame += 1;
+        if !frame.is_multiple_of(self.frame_interval.max(1)) {
+            return Ok(());
+        }
+
+        let (pixels, (width, height)) = fbo.read_color(0);
+        if (width, height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Framebuffer is {}x{} but the Recorder expects {}x{}",
+                    width, height, self.width, self.height
+                ),
+            ));
+        }
+
+        let path = self
+            .output_dir
+            .join(format!("frame_{:06}.rgba", self.written));
+        fs::File::create(path)?.write_all(&pixels)?;
+        self.written += 1;
+
+        Ok(())
+    }
+
+    /// Stops the recording and prints the ffmpeg command that turns the frames into an MP4
+    pub fn finish(&mut self) {
+        self.record = false;
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+
+        let dir = self.output_dir.display();
+        println!("Recorded {} frames to {}", self.written, dir);
+        // The frames are stored bottom row first, hence the vflip
+        println!(
+            "cat {dir}/frame_*.rgba | ffmpeg -f rawvideo -pixel_format rgba -video_size {}x{} -framerate 60 -i - -vf vflip -pix_fmt yuv420p {dir}/output.mp4",
+            self.width, self.height,
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use gl::types::{GLbitfield, GLenum, GLint, GLuint};
+
+use crate::config::Config;
+
+thread_local! {
+    // GL contexts are current on one thread, so is their cache
+    static STATE_CACHE: RefCell<GlStateCache> = RefCell::new(GlStateCache::default());
+}
+
+/// Remembers which capabilities (gl::DEPTH_TEST, ..) are enabled and which program, Vao,
+/// buffers and framebuffers are bound so redundant gl::Enable(..) / gl::Bind*(..) calls are
+/// skipped. State changed by raw gl calls goes out of sync, call `GlStateCache::invalidate()`
+/// afterwards
+#[derive(Debug, Default)]
+pub struct GlStateCache {
+    enabled: HashMap<GLenum, bool>,
+    program: Option<GLuint>,
+    vertex_array: Option<GLuint>,
+    /// By target, e.g: gl::ARRAY_BUFFER
+    buffers: HashMap<GLenum, GLuint>,
+    /// By gl::READ_FRAMEBUFFER and gl::DRAW_FRAMEBUFFER
+    framebuffers: HashMap<GLenum, GLuint>,
+}
+
+impl GlStateCache {
+    pub fn enable(cap: GLenum) {
+        Self::set(cap, true);
+    }
+
+    pub fn disable(cap: GLenum) {
+        Self::set(cap, false);
+    }
+
+    /// Enables or disables `cap` unless it's already known to be in that state
+    pub fn set(cap: GLenum, enable: bool) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if cache.enabled.get(&cap) == Some(&enable) {
+                return;
+            }
+            unsafe {
+                if enable {
+                    gl::Enable(cap);
+                } else {
+                    gl::Disable(cap);
+                }
+            }
+            cache.enabled.insert(cap, enable);
+        });
+    }
+
+    /// Cached state of `cap`, queried with gl::IsEnabled(..) the first time
+    pub fn is_enabled(cap: GLenum) -> bool {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            *cache
+                .enabled
+                .entry(cap)
+                .or_insert_with(|| unsafe { gl::IsEnabled(cap) == gl::TRUE })
+        })
+    }
+
+    /// gl::UseProgram(..) unless `program` is already in use
+    pub fn use_program(program: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if cache.program != Some(program) {
+                unsafe {
+                    gl::UseProgram(program);
+                }
+                cache.program = Some(program);
+            }
+        });
+    }
+
+    /// gl::BindVertexArray(..) unless `vertex_array` is already bound
+    pub fn bind_vertex_array(vertex_array: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if cache.vertex_array != Some(vertex_array) {
+                unsafe {
+                    gl::BindVertexArray(vertex_array);
+                }
+                cache.vertex_array = Some(vertex_array);
+                // The element buffer binding is part of the Vao
+                cache.buffers.remove(&gl::ELEMENT_ARRAY_BUFFER);
+            }
+        });
+    }
+
+    /// gl::BindBuffer(..) unless `buffer` is already bound to `target`
+    pub fn bind_buffer(target: GLenum, buffer: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if cache.buffers.get(&target) != Some(&buffer) {
+                unsafe {
+                    gl::BindBuffer(target, buffer);
+                }
+                cache.buffers.insert(target, buffer);
+            }
+        });
+    }
+
+    /// gl::BindBufferBase(..), which also binds `buffer` to `target` itself
+    pub fn bind_buffer_base(target: GLenum, index: GLuint, buffer: GLuint) {
+        unsafe {
+            gl::BindBufferBase(target, index, buffer);
+        }
+        STATE_CACHE.with_borrow_mut(|cache| cache.buffers.insert(target, buffer));
+    }
+
+    /// gl::BindFramebuffer(..) unless `framebuffer` is already bound to `target`,
+    /// gl::FRAMEBUFFER is both gl::READ_FRAMEBUFFER and gl::DRAW_FRAMEBUFFER
+    pub fn bind_framebuffer(target: GLenum, framebuffer: GLuint) {
+        let targets: &[GLenum] = if target == gl::FRAMEBUFFER {
+            &[gl::READ_FRAMEBUFFER, gl::DRAW_FRAMEBUFFER]
+        } else {
+            &[target]
+        };
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if targets
+                .iter()
+                .all(|target| cache.framebuffers.get(target) == Some(&framebuffer))
+            {
+                return;
+            }
+            unsafe {
+                gl::BindFramebuffer(target, framebuffer);
+            }
+            for target in targets {
+                cache.framebuffers.insert(*target, framebuffer);
+            }
+        });
+    }
+
+    /// Call after gl::DeleteBuffers(..), GL unbinds a deleted buffer from every target
+    pub fn buffer_deleted(buffer: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            for bound in cache.buffers.values_mut().filter(|bound| **bound == buffer) {
+                *bound = 0;
+            }
+        });
+    }
+
+    /// Call after gl::DeleteVertexArrays(..), deleting the bound Vao binds Vao 0
+    pub fn vertex_array_deleted(vertex_array: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            if cache.vertex_array == Some(vertex_array) {
+                cache.vertex_array = Some(0);
+                cache.buffers.remove(&gl::ELEMENT_ARRAY_BUFFER);
+            }
+        });
+    }
+
+    /// Call after gl::DeleteFramebuffers(..), deleting a bound framebuffer binds the default one
+    pub fn framebuffer_deleted(framebuffer: GLuint) {
+        STATE_CACHE.with_borrow_mut(|cache| {
+            for bound in cache
+                .framebuffers
+                .values_mut()
+                .filter(|bound| **bound == framebuffer)
+            {
+                *bound = 0;
+            }
+        });
+    }
+
+    /// Forgets everything, e.g: after a new context was made current
+    pub fn invalidate() {
+        STATE_CACHE.with_borrow_mut(|cache| *cache = GlStateCache::default());
+    }
+}
+
+/// Restores the program in use, and the viewport if it was saved too, when dropped. Passes
+/// that switch to their own program (blur, shadows, deferred lighting, mip generation,
+/// compute dispatches, the debug split view) hold one while they run so the caller's
+/// program and viewport are left alone
+/// e.g: `let _guard = ProgramGuard::save_with_viewport();`
+pub struct ProgramGuard {
+    program: GLint,
+    viewport: Option<[GLint; 4]>,
+}
+
+impl ProgramGuard {
+    pub fn save() -> Self {
+        let mut program: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+        }
+        ProgramGuard {
+            program,
+            viewport: None,
+        }
+    }
+
+    pub fn save_with_viewport() -> Self {
+        let mut viewport: [GLint; 4] = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        ProgramGuard {
+            viewport: Some(viewport),
+            ..Self::save()
+        }
+    }
+}
+
+impl Drop for ProgramGuard {
+    fn drop(&mut self) {
+        GlStateCache::use_program(self.program as GLuint);
+        if let Some([x, y, w, h]) = self.viewport {
+            unsafe {
+                gl::Viewport(x, y, w, h);
+            }
+        }
+    }
+}
+
+/// Values the framebuffer attachments get reset to by gl::Clear(..)
+pub struct ClearState {
+    pub color: (f32, f32, f32, f32),
+    pub depth: f32,
+    pub stencil: i32,
+}
+
+impl ClearState {
+    pub fn from_config(cfg: &Config) -> Self {
+        ClearState {
+            color: cfg.clear_color,
+            depth: 1.0,
+            stencil: 0,
+        }
+    }
+
+    /// Sets the clear values and clears the buffers in `mask` e.g: gl::COLOR_BUFFER_BIT
+    pub fn apply(&self, mask: GLbitfield) {
+        let (r, g, b, a) = self.color;
+        unsafe {
+            gl::ClearColor(r, g, b, a);
+            gl::ClearDepth(self.depth as f64);
+            gl::ClearStencil(self.stencil);
+            gl::Clear(mask);
+        }
+    }
+}
+
+/// Runs `f` with gl::PixelStorei(`pname`, `value`) and restores the previous value afterwards,
+/// e.g: gl::PACK_ALIGNMENT 1 for reading back tightly packed rows
+pub fn with_pixel_store<R>(pname: GLenum, value: GLint, f: impl FnOnce() -> R) -> R {
+    let mut previous: GLint = 0;
+    unsafe {
+        gl::GetIntegerv(pname, &mut previous);
+        gl::PixelStorei(pname, value);
+    }
+    let result = f();
+    unsafe {
+        gl::PixelStorei(pname, previous);
+    }
+    result
+}
+
+/// Filters across cube-map face edges instead of sampling each face on its own (no visible seams)
+pub fn seamless_cubemap_filtering(enable: bool) {
+    GlStateCache::set(gl::TEXTURE_CUBE_MAP_SEAMLESS, enable);
+}
+
+/// How polygons are rasterized, Line and Point are for looking at mesh topology
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+    Point,
+}
+
+impl PolygonMode {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            PolygonMode::Fill => gl::FILL,
+            PolygonMode::Line => gl::LINE,
+            PolygonMode::Point => gl::POINT,
+        }
+    }
+
+    /// Fill -> Line -> Point -> Fill, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            PolygonMode::Fill => PolygonMode::Line,
+            PolygonMode::Line => PolygonMode::Point,
+            PolygonMode::Point => PolygonMode::Fill,
+        }
+    }
+
+    /// Sets gl::PolygonMode(..) for front and back faces
+    pub fn apply(self) {
+        unsafe {
+            gl::PolygonMode(gl::FRONT_AND_BACK, self.to_gl());
+        }
+    }
+}
+
+/// Shorthand for `PolygonMode::Line.apply()` or `PolygonMode::Fill.apply()`
+pub fn set_wireframe(enabled: bool) {
+    if enabled {
+        PolygonMode::Line.apply();
+    } else {
+        PolygonMode::Fill.apply();
+    }
+}
+
+/// Stencil test setup, e.g: write the blackhole disc as 1 and then outline it
+/// by drawing a slightly larger quad where the stencil isn't 1
+#[derive(Clone, Copy, Debug)]
+pub struct StencilState {
+    pub func: GLenum,
+    pub ref_: i32,
+    pub mask: u32,
+    pub sfail: GLenum,
+    pub dpfail: GLenum,
+    pub dppass: GLenum,
+}
+
+impl StencilState {
+    /// Always passes and writes `ref_` wherever something is drawn
+    pub fn write_mask_on(ref_: i32) -> Self {
+        StencilState {
+            func: gl::ALWAYS,
+            ref_,
+            mask: 0xFF,
+            sfail: gl::KEEP,
+            dpfail: gl::KEEP,
+            dppass: gl::REPLACE,
+        }
+    }
+
+    /// Only draws where the stencil is not `ref_`, leaves the stencil untouched
+    pub fn test_mask_on(ref_: i32) -> Self {
+        StencilState {
+            func: gl::NOTEQUAL,
+            ref_,
+            mask: 0xFF,
+            sfail: gl::KEEP,
+            dpfail: gl::KEEP,
+            dppass: gl::KEEP,
+        }
+    }
+
+    /// Enables the stencil test and sets gl::StencilFunc(..) and gl::StencilOp(..)
+    pub fn apply(&self) {
+        GlStateCache::enable(gl::STENCIL_TEST);
+        unsafe {
+            gl::StencilFunc(self.func, self.ref_, self.mask);
+            gl::StencilOp(self.sfail, self.dpfail, self.dppass);
+        }
+    }
+
+    pub fn disable() {
+        GlStateCache::disable(gl::STENCIL_TEST);
+    }
+}
+
+/// Comparison of gl::DepthFunc(..), a fragment passes if `func(fragment, stored)` holds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthFunc {
+    #[default]
+    Less,
+    /// e.g: sky-boxes drawn at the far plane
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Always,
+    Never,
+}
+
+impl DepthFunc {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            DepthFunc::Less => gl::LESS,
+            DepthFunc::LessEqual => gl::LEQUAL,
+            DepthFunc::Greater => gl::GREATER,
+            DepthFunc::GreaterEqual => gl::GEQUAL,
+            DepthFunc::Equal => gl::EQUAL,
+            DepthFunc::NotEqual => gl::NOTEQUAL,
+            DepthFunc::Always => gl::ALWAYS,
+            DepthFunc::Never => gl::NEVER,
+        }
+    }
+}
+
+/// Depth test setup, `DepthState::default()` is what a fresh context starts with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthState {
+    pub enabled: bool,
+    pub func: DepthFunc,
+    /// Whether passing fragments write their depth, off e.g: for transparent geometry
+    pub write_mask: bool,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        DepthState {
+            enabled: false,
+            func: DepthFunc::Less,
+            write_mask: true,
+        }
+    }
+}
+
+impl DepthState {
+    /// Sets the depth test, gl::DepthFunc(..) and gl::DepthMask(..) at once
+    pub fn apply(&self) {
+        GlStateCache::set(gl::DEPTH_TEST, self.enabled);
+        Self::set_func(self.func);
+        unsafe {
+            gl::DepthMask(if self.write_mask { gl::TRUE } else { gl::FALSE });
+        }
+    }
+
+    /// Enables the depth test with `DepthFunc::Less`
+    pub fn enable() {
+        GlStateCache::enable(gl::DEPTH_TEST);
+        Self::set_func(DepthFunc::Less);
+    }
+
+    pub fn disable() {
+        GlStateCache::disable(gl::DEPTH_TEST);
+    }
+
+    pub fn set_func(func: DepthFunc) {
+        unsafe {
+            gl::DepthFunc(func.to_gl());
+        }
+    }
+}
+
+/// Blending setup, `BlendState::default()` is what a fresh context starts with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlendState {
+    pub enabled: bool,
+    /// Source factor of gl::BlendFunc(..), e.g: gl::SRC_ALPHA
+    pub src: GLenum,
+    /// Destination factor of gl::BlendFunc(..), e.g: gl::ONE_MINUS_SRC_ALPHA
+    pub dst: GLenum,
+    /// e.g: gl::FUNC_ADD or gl::MAX
+    pub equation: GLenum,
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        BlendState {
+            enabled: false,
+            src: gl::ONE,
+            dst: gl::ZERO,
+            equation: gl::FUNC_ADD,
+        }
+    }
+}
+
+impl BlendState {
+    /// Adds the fragment to what's already there, e.g: the glow around the blackhole
+    pub fn additive() -> Self {
+        BlendState {
+            enabled: true,
+            src: gl::ONE,
+            dst: gl::ONE,
+            equation: gl::FUNC_ADD,
+        }
+    }
+
+    /// Regular transparency for non-premultiplied colors
+    pub fn alpha() -> Self {
+        BlendState {
+            enabled: true,
+            src: gl::SRC_ALPHA,
+            dst: gl::ONE_MINUS_SRC_ALPHA,
+            equation: gl::FUNC_ADD,
+        }
+    }
+
+    /// Enables or disables blending and sets gl::BlendFunc(..) and gl::BlendEquation(..)
+    pub fn apply(&self) {
+        GlStateCache::set(gl::BLEND, self.enabled);
+        unsafe {
+            gl::BlendFunc(self.src, self.dst);
+            gl::BlendEquation(self.equation);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::error::Error;
+
+use gl::types::GLint;
+use glam::{Mat4, Vec3};
+
+use crate::{
+    objects::{Framebuffer, Program, Shader, TextureCubemap, Uniform},
+    render_state::ProgramGuard,
+};
+
+/// GLSL `float point_shadow(samplerCube, world_pos, light_pos, far_plane, bias)`,
+/// splice into the lit shader after the `#version` line or `#include "point_shadow.glsl"`
+pub const POINT_SHADOW_GLSL: &str = include_str!("point_shadow.glsl");
+
+/// Omnidirectional shadow map of a point light, all six faces are rendered in a single
+/// pass by a geometry shader (layered rendering)
+pub struct PointShadowMap {
+    pub cube_fbo: Framebuffer,
+    pub cube_depth: TextureCubemap,
+    pub size: u32,
+    pub light_pos: [f32; 3],
+    pub near: f32,
+    pub far: f32,
+    program: Program,
+    u_model: Uniform,
+    u_shadow_matrices: Uniform,
+    u_light_pos: Uniform,
+    u_far: Uniform,
+}
+
+impl PointShadowMap {
+    pub fn new(
+        size: u32,
+        light_pos: [f32; 3],
+        near: f32,
+        far: f32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut cube_depth = TextureCubemap::generate();
+        cube_depth.allocate_depth(size);
+
+        let cube_fbo = Framebuffer::generate(size, size);
+        cube_fbo.attach_depth_cubemap(&cube_depth);
+        cube_fbo.check_status()?;
+
+        let program = Program::from_shaders(&[
+            Shader::from_source_str(include_str!("point_shadow_vert.glsl"), gl::VERTEX_SHADER)?,
+            Shader::geometry_from_source_str(include_str!("point_shadow_geom.glsl"))?,
+            Shader::from_source_str(include_str!("point_shadow_frag.glsl"), gl::FRAGMENT_SHADER)?,
+        ])?;
+
+        Ok(PointShadowMap {
+            cube_fbo,
+            cube_depth,
+            size,
+            light_pos,
+            near,
+            far,
+            u_model: Uniform::new(program.id(), "u_model")?,
+            u_shadow_matrices: Uniform::new(program.id(), "u_shadow_matrices")?,
+            u_light_pos: Uniform::new(program.id(), "u_light_pos")?,
+            u_far: Uniform::new(program.id(), "u_far")?,
+            program,
+        })
+    }
+
+    /// View projection matrices of the six faces in GL_TEXTURE_CUBE_MAP_POSITIVE_X .. NEGATIVE_Z order
+    pub fn face_matrices(&self) -> [Mat4; 6] {
+        let proj = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, self.near, self.far);
+        let eye = Vec3::from(self.light_pos);
+        let faces = [
+            (Vec3::X, Vec3::NEG_Y),
+            (Vec3::NEG_X, Vec3::NEG_Y),
+            (Vec3::Y, Vec3::Z),
+            (Vec3::NEG_Y, Vec3::NEG_Z),
+            (Vec3::Z, Vec3::NEG_Y),
+            (Vec3::NEG_Z, Vec3::NEG_Y),
+        ];
+        faces.map(|(dir, up)| proj * Mat4::look_at_rh(eye, eye + dir, up))
+    }
+
+    /// Renders the depth cube-map, `draw_scene` draws every shadow caster after writing its
+    /// model matrix (column-major) to the given `u_model` uniform with gl::UniformMatrix4fv(..).
+    /// Casters need their position at `layout(location = 0)` as a vec3, and gl::DEPTH_TEST
+    /// has to be enabled
+    pub fn render(&self, mut draw_scene: impl FnMut(&Uniform)) {
+        let _guard = ProgramGuard::save_with_viewport();
+        unsafe {
+            gl::Viewport(0, 0, self.size as GLint, self.size as GLint);
+        }
+
+        self.cube_fbo.bind();
+        self.program.set();
+
+        let matrices = self.face_matrices().map(|m| m.to_cols_array());
+        let [x, y, z] = self.light_pos;
+        unsafe {
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            gl::UniformMatrix4fv(
+                self.u_shadow_matrices.id,
+                6,
+                gl::FALSE,
+                matrices.as_ptr() as *const f32,
+            );
+            gl::Uniform3f(self.u_light_pos.id, x, y, z);
+        }
+        self.u_far.set_1f(self.far);
+
+        draw_scene(&self.u_model);
+
+        self.cube_fbo.unbind();
+    }
+
+    /// Binds the depth cube-map for sampling with `point_shadow(..)`
+    pub fn bind(&self, slot: u32) {
+        self.cube_depth.bind(slot);
+    }
+}
@@ -0,0 +1,9 @@
+// Gram-Schmidt orthonormalization of a tangent frame, the normal is kept as the reference axis.
+// Mirrors math::orthonormalize_tbn on the Rust side
+mat3 orthonormalize_tbn(vec3 t, vec3 b, vec3 n) {
+  n = normalize(n);
+  t = normalize(t - n * dot(n, t));
+  b = normalize(b - n * dot(n, b) - t * dot(t, b));
+
+  return mat3(t, b, n);
+}
@@ -0,0 +1,36 @@
+use sdl2::{
+    Sdl,
+    video::{self, GLContext, Window},
+};
+
+use crate::{gpu_info::GpuCaps, render_state::GlStateCache};
+
+/// Creates a hidden 1x1 window with a current OpenGL 3.3 core context and loads the GL
+/// functions, for `#[test]`s of the objects module. Needs a display, so these tests are
+/// `#[ignore]`d and run with `cargo test -- --ignored --test-threads=1` since SDL can only be
+/// initialized once at a time. Keep all three values alive for the duration of the test
+pub fn create_gl_context() -> (Sdl, Window, GLContext) {
+    let sdl = sdl2::init().expect("failed to initialize SDL");
+    let video_subsystem = sdl
+        .video()
+        .expect("failed to initialize the SDL video subsystem");
+
+    let gl_attr = video_subsystem.gl_attr();
+    gl_attr.set_context_profile(video::GLProfile::Core);
+    gl_attr.set_context_version(3, 3);
+
+    let window = video_subsystem
+        .window("test", 1, 1)
+        .opengl()
+        .hidden()
+        .build()
+        .expect("failed to create the test window");
+    let gl_context = window
+        .gl_create_context()
+        .expect("failed to create the GL context");
+    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    GpuCaps::refresh();
+    GlStateCache::invalidate();
+
+    (sdl, window, gl_context)
+}
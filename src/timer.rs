@@ -0,0 +1,103 @@
+use std::{collections::VecDeque, time::Instant};
+
+/// Frames `DeltaTimer::fps` averages over
+const FPS_WINDOW: usize = 60;
+
+/// Simulation clock that can be paused and sped up/slowed down
+pub struct TimeController {
+    pub time: f32,
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl TimeController {
+    pub fn new() -> Self {
+        TimeController {
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    /// Advances the clock by `delta * speed` unless paused
+    pub fn tick(&mut self, delta: f32) {
+        if !self.paused {
+            self.time += delta * self.speed;
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn set_speed(&mut self, s: f32) {
+        self.speed = s.max(0.0);
+    }
+}
+
+impl Default for TimeController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wall clock time between frames, call `tick` once per frame
+pub struct DeltaTimer {
+    start: Instant,
+    last: Instant,
+    /// Deltas of the last FPS_WINDOW ticks, oldest first
+    deltas: VecDeque<f32>,
+}
+
+impl DeltaTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        DeltaTimer {
+            start: now,
+            last: now,
+            deltas: VecDeque::with_capacity(FPS_WINDOW),
+        }
+    }
+
+    /// Seconds since the last `tick`, or since `new` for the first one
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last).as_secs_f32();
+        self.last = now;
+
+        if self.deltas.len() == FPS_WINDOW {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+        delta
+    }
+
+    /// Seconds since `new`
+    pub fn elapsed_total(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    /// Average frame rate over the last 60 ticks, 0 before the first one
+    pub fn fps(&self) -> f32 {
+        let total: f32 = self.deltas.iter().sum();
+        if total > 0.0 {
+            self.deltas.len() as f32 / total
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for DeltaTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,50 +1,237 @@
+use std::ffi::c_void;
+
 use sdl2::{
     EventPump, Sdl,
-    video::{self, GLContext, SwapInterval, Window},
+    event::Event,
+    video::{self, FullscreenType, GLContext, SwapInterval, Window},
+};
+
+use crate::{
+    debug::enable_debug_output,
+    gpu_info::{GpuCaps, GpuInfo},
+    objects::load_spirv_with,
+    render_state::{GlStateCache, seamless_cubemap_filtering},
 };
 
+/// Swap interval of the window, see Winsdl::set_vsync
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VsyncMode {
+    /// Swaps right away, may tear. For profiling
+    Off,
+    /// Waits for the vertical blank
+    #[default]
+    On,
+    /// Like On, but a late frame swaps right away instead of waiting for the next blank.
+    /// Not supported by every driver
+    Adaptive,
+}
+
+impl From<VsyncMode> for SwapInterval {
+    fn from(mode: VsyncMode) -> Self {
+        match mode {
+            VsyncMode::Off => SwapInterval::Immediate,
+            VsyncMode::On => SwapInterval::VSync,
+            VsyncMode::Adaptive => SwapInterval::LateSwapTearing,
+        }
+    }
+}
+
+/// Context settings for Winsdl::new_with_options
+#[derive(Clone, Copy, Debug)]
+pub struct WinsdlOptions {
+    pub gl_major: u8,
+    pub gl_minor: u8,
+    /// Core profile if set, compatibility profile otherwise
+    pub core_profile: bool,
+    /// Samples per pixel of the default framebuffer, 0 disables MSAA
+    pub msaa_samples: u8,
+}
+
+impl Default for WinsdlOptions {
+    /// OpenGL 4.1 core without MSAA, the newest context macOS provides
+    fn default() -> Self {
+        WinsdlOptions {
+            gl_major: 4,
+            gl_minor: 1,
+            core_profile: true,
+            msaa_samples: 0,
+        }
+    }
+}
+
 pub struct Winsdl {
     pub sdl: Sdl,
     pub window: Window,
     pub gl_context: GLContext,
     pub gl: (),
     pub event_pump: EventPump,
+    is_fullscreen: bool,
+    /// Size the viewport was last set to by `on_resize`
+    size: (u32, u32),
 }
 
 impl Winsdl {
-    pub fn new(width: usize, height: usize) -> Result<Self, &'static str> {
-        let sdl = sdl2::init().unwrap();
-        let video_subsystem = sdl.video().unwrap();
+    /// OpenGL 4.1 core without MSAA, see `new_with_options`
+    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+        Self::new_with_options(width as u32, height as u32, WinsdlOptions::default())
+    }
+
+    /// Like `new` with a specific context version, profile and MSAA sample count, e.g: 4.3
+    /// for compute shaders
+    pub fn new_with_options(width: u32, height: u32, opts: WinsdlOptions) -> Result<Self, String> {
+        let sdl = sdl2::init()?;
+        Self::from_context(sdl, width, height, opts)
+    }
+
+    /// Creates the window and GL context from an already initialized SDL context,
+    /// SDL can only be initialized once per process (e.g: test harnesses)
+    pub fn from_context(
+        sdl: Sdl,
+        width: u32,
+        height: u32,
+        opts: WinsdlOptions,
+    ) -> Result<Self, String> {
+        Self::create(sdl, width, height, false, opts)
+    }
+
+    /// Same as `new` but the window is never shown, for headless rendering (CI screenshots)
+    pub fn new_offscreen(width: u32, height: u32) -> Result<Self, String> {
+        let sdl = sdl2::init()?;
+        Self::create(sdl, width, height, true, WinsdlOptions::default())
+    }
+
+    fn create(
+        sdl: Sdl,
+        width: u32,
+        height: u32,
+        hidden: bool,
+        opts: WinsdlOptions,
+    ) -> Result<Self, String> {
+        let video_subsystem = sdl.video()?;
 
         let gl_attr = video_subsystem.gl_attr();
-        gl_attr.set_context_profile(video::GLProfile::Core);
-        gl_attr.set_context_version(3, 3);
+        gl_attr.set_context_profile(if opts.core_profile {
+            video::GLProfile::Core
+        } else {
+            video::GLProfile::Compatibility
+        });
+        gl_attr.set_context_version(opts.gl_major, opts.gl_minor);
+        if opts.msaa_samples > 0 {
+            gl_attr.set_multisample_buffers(1);
+            gl_attr.set_multisample_samples(opts.msaa_samples);
+        }
+        if cfg!(debug_assertions) {
+            gl_attr.set_context_flags().debug().set();
+        }
 
-        let window = video_subsystem
-            .window("OpenGL", width as u32, height as u32)
-            .resizable()
-            .opengl()
-            .build()
-            .unwrap();
+        let mut window_builder = video_subsystem.window("OpenGL", width, height);
+        window_builder.resizable().opengl();
+        if hidden {
+            window_builder.hidden();
+        }
+        let window = window_builder.build().map_err(|e| e.to_string())?;
 
-        let gl_context = window.gl_create_context().unwrap();
+        let gl_context = window.gl_create_context()?;
         let gl = gl::load_with(|s| {
             video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void
         });
 
-        window
-            .subsystem()
-            .gl_set_swap_interval(SwapInterval::VSync)
-            .unwrap();
+        let gpu_info = GpuInfo::query();
+        GpuCaps::store(&gpu_info);
+        GlStateCache::invalidate();
+        if cfg!(debug_assertions) {
+            enable_debug_output();
+        }
+        if gpu_info.supports_seamless_cubemap() {
+            seamless_cubemap_filtering(true);
+        }
 
-        let event_pump: sdl2::EventPump = sdl.event_pump().unwrap();
+        let event_pump: sdl2::EventPump = sdl.event_pump()?;
 
-        Ok(Winsdl {
+        let mut winsdl = Winsdl {
             sdl,
             window,
             gl_context,
             gl,
             event_pump,
-        })
+            is_fullscreen: false,
+            size: (0, 0),
+        };
+        load_spirv_with(|s| winsdl.gl_get_proc_address(s));
+        winsdl.set_vsync(VsyncMode::default())?;
+        winsdl.on_resize(width as i32, height as i32);
+        Ok(winsdl)
+    }
+
+    /// Errors if the driver doesn't support `mode`, the previous mode stays then
+    pub fn set_vsync(&self, mode: VsyncMode) -> Result<(), String> {
+        self.window.subsystem().gl_set_swap_interval(mode)
+    }
+
+    /// e.g: `winsdl.set_title(&format!("OpenGL | {:.1} fps", fps))`, errors if `title`
+    /// contains a nul byte
+    pub fn set_title(&mut self, title: &str) -> Result<(), String> {
+        self.window.set_title(title).map_err(|e| e.to_string())
+    }
+
+    /// Address of the GL function `proc` for the window's context, the `gl` crate is already
+    /// loaded with it in `new`. For another loader, e.g: with glow:
+    /// `unsafe { glow::Context::from_loader_function(|s| winsdl.gl_get_proc_address(s)) }`.
+    /// Its calls bypass `GlStateCache`, call `GlStateCache::invalidate()` after mixing them
+    pub fn gl_get_proc_address(&self, proc: &str) -> *const c_void {
+        self.window.subsystem().gl_get_proc_address(proc) as *const c_void
+    }
+
+    /// Every event that arrived since the last call, doesn't block
+    pub fn poll_events(&mut self) -> Vec<Event> {
+        self.event_pump.poll_iter().collect()
+    }
+
+    /// Blocks until the next event, e.g: while there's nothing to render
+    pub fn wait_event(&mut self) -> Event {
+        self.event_pump.wait_event()
+    }
+
+    /// Locks the cursor to the window and hides it so Event::MouseMotion { xrel, yrel, .. }
+    /// keeps reporting relative motion at the screen edges, e.g: for the Camera.
+    /// `false` releases and shows it again
+    pub fn capture_mouse(&mut self, capture: bool) {
+        let mouse = self.sdl.mouse();
+        mouse.set_relative_mouse_mode(capture);
+        mouse.show_cursor(!capture);
+    }
+
+    /// Switches between borderless desktop fullscreen and a window and resizes the viewport
+    /// to the new drawable size, update anything sized to `current_size` afterwards
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let fullscreen_type = if self.is_fullscreen {
+            FullscreenType::Off
+        } else {
+            FullscreenType::Desktop
+        };
+        self.window.set_fullscreen(fullscreen_type)?;
+        self.is_fullscreen = !self.is_fullscreen;
+
+        let (width, height) = self.window.drawable_size();
+        self.on_resize(width as i32, height as i32);
+        Ok(())
+    }
+
+    /// Call on WindowEvent::Resized, stores the new size and sets the viewport to it
+    pub fn on_resize(&mut self, width: i32, height: i32) {
+        self.size = (width.max(0) as u32, height.max(0) as u32);
+        unsafe {
+            gl::Viewport(0, 0, width, height);
+        }
+    }
+
+    /// (width, height) of the viewport
+    pub fn current_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// `current_size` as floats, e.g: for u_resolution
+    pub fn current_size_f32(&self) -> (f32, f32) {
+        (self.size.0 as f32, self.size.1 as f32)
     }
 }